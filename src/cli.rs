@@ -0,0 +1,294 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
+
+use crate::export::Column;
+use crate::filter::Filter;
+use crate::guild::Permissions;
+use crate::mute::MuteDuration;
+use crate::snowflake::Snowflake;
+use crate::token::Token;
+
+/// Manage the guilds of a Discord account.
+///
+/// Runs the interactive menu when no subcommand is given.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Config file to use instead of the one in the user's config directory.
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Journal file to append actions to instead of the default one.
+    #[arg(long, global = true)]
+    pub journal: Option<PathBuf>,
+
+    /// File to read the token from. Should be readable only by you.
+    #[arg(long, global = true, conflicts_with = "token_stdin")]
+    pub token_file: Option<PathBuf>,
+
+    /// Read the token from the first line of stdin.
+    #[arg(long, global = true)]
+    pub token_stdin: bool,
+
+    /// Send the token as a bot token. Without this, a token Discord rejects
+    /// as a user token is retried as a bot token.
+    #[arg(long, global = true)]
+    pub bot: bool,
+
+    /// Which token in the vault to use, if it holds more than one.
+    #[arg(short, long, global = true)]
+    pub account: Option<String>,
+
+    /// Don't ask for confirmation before doing anything destructive.
+    #[arg(short, long, global = true)]
+    pub yes: bool,
+
+    /// Show what would be changed without changing anything.
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// How many guilds a bulk operation works on at once [default: 4].
+    #[arg(short = 'j', long, global = true, value_parser = clap::value_parser!(u16).range(1..))]
+    pub concurrency: Option<u16>,
+
+    /// How to print results.
+    #[arg(short, long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    /// The token itself. Deprecated, since other users can see it in the
+    /// process list: use `DISCORD_TOKEN`, `--token-file` or `--token-stdin`.
+    #[arg(hide = true, conflicts_with_all = ["token_file", "token_stdin"])]
+    pub token: Option<Token>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Check the token and show who it belongs to.
+    Whoami,
+    /// List, leave or mute guilds.
+    #[command(subcommand)]
+    Guilds(GuildsCommand),
+    /// Manage the guilds that must never be left.
+    #[command(subcommand)]
+    Allowlist(AllowlistCommand),
+    /// Store tokens encrypted under a passphrase.
+    #[command(subcommand)]
+    Vault(VaultCommand),
+    /// Show when and where a Discord ID was generated. Needs no token.
+    Decode { id: Snowflake },
+    /// Browse, search and multi-select guilds to leave in a full-screen UI.
+    #[cfg(feature = "tui")]
+    Tui,
+    /// Continue an interrupted mass leave or mute, retrying the guilds that
+    /// failed.
+    Resume {
+        /// The run's ID, as logged when it started.
+        run_id: String,
+
+        /// Also leave guilds on the allowlist, after typing each one's name.
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum GuildsCommand {
+    /// List every guild the account is in.
+    List {
+        /// Only list guilds matching this filter, e.g. `members > 1000 and not owner`.
+        #[arg(short, long)]
+        filter: Option<Filter>,
+
+        /// Order the guilds by this instead of by ID.
+        #[arg(short, long, value_enum)]
+        sort: Option<GuildSort>,
+
+        /// Comma-separated columns to include, for every output but `text`.
+        #[arg(short, long, value_enum, value_delimiter = ',', default_values_t = Column::ALL.to_vec())]
+        columns: Vec<Column>,
+    },
+    /// Leave one or more guilds by ID, or every guild matching a filter.
+    Leave {
+        #[arg(required_unless_present = "filter")]
+        ids: Vec<Snowflake>,
+
+        /// Leave every guild matching this filter, e.g. `name ~ /crypto/i and not owner`.
+        #[arg(short, long)]
+        filter: Option<Filter>,
+
+        /// Also leave guilds on the allowlist, after typing each one's name.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Mute guilds instead of leaving them, along with their `@everyone`
+    /// and role mentions. Works on owned and allowlisted guilds too.
+    Mute {
+        #[arg(required_unless_present = "filter")]
+        ids: Vec<Snowflake>,
+
+        /// Mute every guild matching this filter, e.g. `inactive > 30`.
+        #[arg(short, long)]
+        filter: Option<Filter>,
+
+        /// Unmute them again after this long, e.g. `8h`, `7d` or `2w`.
+        /// Without it they stay muted until unmuted.
+        #[arg(long = "for", value_name = "DURATION")]
+        duration: Option<MuteDuration>,
+    },
+    /// Undo `guilds mute`, turning notifications and mentions back on.
+    Unmute {
+        #[arg(required_unless_present = "filter")]
+        ids: Vec<Snowflake>,
+
+        /// Unmute every guild matching this filter.
+        #[arg(short, long)]
+        filter: Option<Filter>,
+    },
+    /// Rank guilds by how long it has been since anyone posted in them, and
+    /// flag the dead ones.
+    Activity {
+        /// How many days without a message make a guild dead.
+        #[arg(long, default_value_t = 90)]
+        inactive_days: u32,
+
+        /// Leave the dead guilds instead of listing every guild.
+        #[arg(long)]
+        leave: bool,
+    },
+    /// Leave guilds that are too small, never granted the permissions the
+    /// account needs, or are owned by blocked users. Always shows what it is
+    /// about to leave first.
+    #[command(group(ArgGroup::new("criteria").required(true).multiple(true)))]
+    Prune {
+        /// Leave guilds with fewer members than this.
+        #[arg(long, group = "criteria")]
+        min_members: Option<u64>,
+
+        /// Leave guilds that haven't granted these permissions, given as
+        /// names separated by commas (e.g. `send_messages,embed_links`) or
+        /// as a raw bitfield.
+        #[arg(long, group = "criteria")]
+        required_permissions: Option<Permissions>,
+
+        /// How many days after joining a guild has to grant the required
+        /// permissions before it is left. 0 leaves it right away.
+        #[arg(long, default_value_t = 7, requires = "required_permissions")]
+        grace_days: u32,
+
+        /// Leave guilds owned by this user. Repeat it or separate IDs with commas.
+        #[arg(
+            long = "blocked-owner",
+            value_name = "USER_ID",
+            value_delimiter = ',',
+            group = "criteria"
+        )]
+        blocked_owners: Vec<Snowflake>,
+
+        /// The most guilds one run may leave. Run it again for the rest.
+        #[arg(long, default_value_t = 25, value_parser = clap::value_parser!(u32).range(1..))]
+        limit: u32,
+    },
+    /// Hand a guild you own over to another member.
+    Transfer {
+        guild_id: Snowflake,
+        /// The member who will become the new owner.
+        user_id: Snowflake,
+        /// Two-factor authentication code, if your account has 2FA enabled.
+        #[arg(long)]
+        mfa_code: Option<String>,
+    },
+    /// Permanently delete a guild you own.
+    Delete {
+        guild_id: Snowflake,
+        /// Two-factor authentication code, if your account has 2FA enabled.
+        #[arg(long)]
+        mfa_code: Option<String>,
+    },
+}
+
+/// What `guilds list --sort` orders by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GuildSort {
+    Name,
+    Members,
+    /// When you joined, oldest first.
+    Joined,
+    /// When the guild was created, oldest first.
+    Created,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AllowlistCommand {
+    /// Protect guilds by ID or by a name pattern.
+    Add(AllowlistEntries),
+    /// Stop protecting guilds by ID or by a name pattern.
+    Remove(AllowlistEntries),
+    /// Show every protected guild ID and name pattern.
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum VaultCommand {
+    /// Store a token, read from the usual token sources, under a name.
+    Add { name: String },
+    /// Forget a stored token.
+    Remove { name: String },
+    /// Show the names of the stored tokens.
+    List,
+}
+
+#[derive(Debug, clap::Args)]
+#[command(arg_required_else_help = true)]
+pub struct AllowlistEntries {
+    /// Guild IDs.
+    pub ids: Vec<Snowflake>,
+
+    /// Case-insensitive regex matched against guild names.
+    #[arg(short, long = "pattern")]
+    pub patterns: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    /// One JSON object per line.
+    Ndjson,
+    Csv,
+    /// An aligned plain-text table.
+    Table,
+    /// A Markdown table.
+    Markdown,
+}
+
+impl OutputFormat {
+    pub fn is_json(self) -> bool {
+        matches!(self, OutputFormat::Json | OutputFormat::Ndjson)
+    }
+}
+
+/// Process exit codes, so scripts can tell failures apart. Clap exits with
+/// 2 on its own when the command line can't be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Success = 0,
+    /// A request failed or something else went wrong.
+    Failure = 1,
+    /// No token was found, or Discord rejected it.
+    InvalidToken = 3,
+    /// A bulk operation finished, but some of it failed.
+    Partial = 4,
+    /// The user declined a confirmation prompt.
+    Aborted = 5,
+}
+
+impl From<Exit> for ExitCode {
+    fn from(exit: Exit) -> Self {
+        ExitCode::from(exit as u8)
+    }
+}
@@ -0,0 +1,647 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream, TryStreamExt};
+use reqwest::{header, Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use tracing::{debug, info};
+
+use crate::config::Allowlist;
+use crate::error::{Error, Result};
+use crate::guild::{Channel, Guild, GuildDetails, Member};
+use crate::journal::{Entry, Journal, Operation};
+use crate::mute::NotificationSettings;
+use crate::ratelimit::{RateLimited, RateLimiter, Route};
+use crate::snowflake::Snowflake;
+use crate::token::Token;
+use crate::user::CurrentUser;
+
+pub const DEFAULT_BASE_URL: &str = "https://discord.com/api";
+pub const DEFAULT_API_VERSION: u8 = 10;
+/// The most guilds `/users/@me/guilds` returns in one response.
+pub const GUILDS_PAGE_LIMIT: usize = 200;
+pub const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// The user agent Discord requires from bots: `DiscordBot ($url, $version)`.
+fn bot_user_agent() -> String {
+    let url = [env!("CARGO_PKG_REPOSITORY"), env!("CARGO_PKG_HOMEPAGE")]
+        .into_iter()
+        .find(|url| !url.is_empty())
+        .unwrap_or(env!("CARGO_PKG_NAME"));
+    format!("DiscordBot ({}, {})", url, env!("CARGO_PKG_VERSION"))
+}
+
+/// A single connection pool plus everything needed to talk to the Discord API.
+#[derive(Clone)]
+pub struct DiscordClient {
+    http: reqwest::Client,
+    token: Arc<Token>,
+    base_url: String,
+    api_version: u8,
+    /// Overrides the default user agent for the token's kind.
+    user_agent: Option<String>,
+    max_retries: u32,
+    concurrency: usize,
+    dry_run: bool,
+    allowlist: Arc<Allowlist>,
+    journal: Option<Arc<Journal>>,
+    ratelimiter: Arc<RateLimiter>,
+    current_user: Arc<OnceLock<CurrentUser>>,
+    /// The current user's member object per guild ID.
+    members: Arc<Mutex<HashMap<Snowflake, Member>>>,
+}
+
+pub struct DiscordClientBuilder {
+    token: Token,
+    base_url: String,
+    api_version: u8,
+    user_agent: Option<String>,
+    timeout: Duration,
+    connect_timeout: Duration,
+    max_retries: u32,
+    concurrency: usize,
+    dry_run: bool,
+    allowlist: Allowlist,
+    journal: Option<Journal>,
+}
+
+impl DiscordClientBuilder {
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn api_version(mut self, api_version: u8) -> Self {
+        self.api_version = api_version;
+        self
+    }
+
+    /// Replaces the default user agent, which depends on whether the token
+    /// belongs to a user or a bot.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// How many times a rate-limited request is retried before giving up.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// How many requests a bulk operation keeps in flight at once. Requests
+    /// still queue up behind the rate limiter.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Log mutating requests instead of sending them, answering each with a
+    /// simulated `204 No Content`.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Guilds that [`DiscordClient::leave_guild`] must refuse to leave.
+    pub fn allowlist(mut self, allowlist: Allowlist) -> Self {
+        self.allowlist = allowlist;
+        self
+    }
+
+    /// Where to record every mutating request.
+    pub fn journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Applies overrides from `DISCORD_API_BASE_URL`, `DISCORD_API_VERSION`,
+    /// `DISCORD_USER_AGENT`, `DISCORD_TIMEOUT` and `DISCORD_CONNECT_TIMEOUT`
+    /// (both in seconds), `DISCORD_MAX_RETRIES` and `DISCORD_CONCURRENCY`,
+    /// ignoring any that are unset or unparsable.
+    pub fn env_overrides(mut self) -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            std::env::var(name).ok()?.trim().parse().ok()
+        }
+
+        if let Some(base_url) = var::<String>("DISCORD_API_BASE_URL") {
+            self = self.base_url(base_url);
+        }
+        if let Some(api_version) = var("DISCORD_API_VERSION") {
+            self = self.api_version(api_version);
+        }
+        if let Some(user_agent) = var::<String>("DISCORD_USER_AGENT") {
+            self = self.user_agent(user_agent);
+        }
+        if let Some(secs) = var("DISCORD_TIMEOUT") {
+            self = self.timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = var("DISCORD_CONNECT_TIMEOUT") {
+            self = self.connect_timeout(Duration::from_secs(secs));
+        }
+        if let Some(max_retries) = var("DISCORD_MAX_RETRIES") {
+            self = self.max_retries(max_retries);
+        }
+        if let Some(concurrency) = var("DISCORD_CONCURRENCY") {
+            self = self.concurrency(concurrency);
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<DiscordClient> {
+        let http = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout)
+            .build()?;
+
+        Ok(DiscordClient {
+            http,
+            token: Arc::new(self.token),
+            base_url: self.base_url,
+            api_version: self.api_version,
+            user_agent: self.user_agent,
+            max_retries: self.max_retries,
+            concurrency: self.concurrency,
+            dry_run: self.dry_run,
+            allowlist: Arc::new(self.allowlist),
+            journal: self.journal.map(Arc::new),
+            ratelimiter: Arc::default(),
+            current_user: Arc::default(),
+            members: Arc::default(),
+        })
+    }
+}
+
+impl DiscordClient {
+    pub fn builder(token: Token) -> DiscordClientBuilder {
+        DiscordClientBuilder {
+            token,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            api_version: DEFAULT_API_VERSION,
+            user_agent: None,
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+            max_retries: 5,
+            concurrency: 4,
+            dry_run: false,
+            allowlist: Allowlist::default(),
+            journal: None,
+        }
+    }
+
+    pub fn is_bot(&self) -> bool {
+        self.token.is_bot()
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn allowlist(&self) -> &Allowlist {
+        &self.allowlist
+    }
+
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_deref()
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/v{}{}", self.base_url, self.api_version, path)
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let user_agent = match &self.user_agent {
+            Some(user_agent) => user_agent.clone(),
+            None if self.token.is_bot() => bot_user_agent(),
+            None => DEFAULT_USER_AGENT.to_string(),
+        };
+        self.http
+            .request(method, self.url(path))
+            .header(header::AUTHORIZATION, self.token.authorization())
+            .header(header::USER_AGENT, user_agent)
+    }
+
+    async fn send(&self, method: Method, path: &str) -> Result<Response> {
+        self.send_with(method, path, |request| request).await
+    }
+
+    /// Sends a request through the rate limiter, transparently retrying 429s
+    /// and turning any other non-2xx response into an [`Error`].
+    async fn send_with(
+        &self,
+        method: Method,
+        path: &str,
+        build: impl FnOnce(RequestBuilder) -> RequestBuilder,
+    ) -> Result<Response> {
+        let route = Route::new(&method, path);
+        let mutating = method != Method::GET;
+        let request = build(self.request(method, path));
+
+        if self.dry_run && mutating {
+            return Self::simulate(request);
+        }
+        let mut attempt = 0;
+
+        loop {
+            let mut ticket = self.ratelimiter.acquire(&route).await;
+            let response = request
+                .try_clone()
+                .expect("request bodies are always buffered")
+                .send()
+                .await?;
+            self.ratelimiter
+                .update(&route, &mut ticket, response.headers());
+            drop(ticket);
+
+            if response.status() != StatusCode::TOO_MANY_REQUESTS {
+                if !response.status().is_success() {
+                    return Err(Error::from_response(response).await);
+                }
+                return Ok(response);
+            }
+
+            let headers = response.headers().clone();
+            let body: Option<RateLimited> = serde_json::from_slice(&response.bytes().await?).ok();
+            let retry_after = self.ratelimiter.rate_limited(&headers, body.as_ref());
+
+            attempt += 1;
+            if attempt > self.max_retries {
+                return Err(Error::RateLimited {
+                    retry_after,
+                    global: body.is_some_and(|b| b.global),
+                });
+            }
+            tokio::time::sleep(retry_after).await;
+        }
+    }
+
+    /// Logs the request that would have been sent and fakes a successful
+    /// response to it.
+    fn simulate(request: RequestBuilder) -> Result<Response> {
+        let request = request.build()?;
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(String::from_utf8_lossy);
+
+        match body {
+            Some(body) => info!(
+                "[dry run] Would send {} {} {}",
+                request.method(),
+                request.url(),
+                body
+            ),
+            None => info!(
+                "[dry run] Would send {} {}",
+                request.method(),
+                request.url()
+            ),
+        }
+
+        let response = http::Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Vec::new())
+            .expect("a bare 204 response is always valid");
+        Ok(Response::from(response))
+    }
+
+    fn record(&self, operation: Operation, guild: &Guild, result: &Result<Response>) {
+        if let Some(journal) = &self.journal {
+            journal.record(&Entry::new(
+                operation,
+                guild.id,
+                Some(&guild.name),
+                result,
+                self.dry_run,
+            ));
+        }
+    }
+
+    async fn json<T: DeserializeOwned>(response: Response) -> Result<T> {
+        Ok(serde_json::from_slice(&response.bytes().await?)?)
+    }
+
+    /// Validates the token, returning the user it belongs to and keeping it
+    /// for [`DiscordClient::current_user`].
+    pub async fn check_discord_token(&self) -> Result<CurrentUser> {
+        info!("Checking token...");
+
+        let response = self.send(Method::GET, "/users/@me").await?;
+
+        let user: CurrentUser = Self::json(response).await?;
+        info!("Token is valid! Welcome back {} ({})", user, user.id);
+        let _ = self.current_user.set(user.clone());
+        Ok(user)
+    }
+
+    /// Validates the token like [`DiscordClient::check_discord_token`]. If
+    /// Discord rejects it as a user token, tries it once more as a bot token
+    /// and, if that works, returns a client that sends it as one from then on.
+    pub async fn detect_bot(self) -> Result<Self> {
+        let err = match self.check_discord_token().await {
+            Ok(_) => return Ok(self),
+            Err(e) if e.status() == Some(StatusCode::UNAUTHORIZED) && !self.is_bot() => e,
+            Err(e) => return Err(e),
+        };
+
+        debug!("The token was rejected as a user token, trying it as a bot token");
+        let bot = Self {
+            token: Arc::new(self.token.to_bot()),
+            current_user: Arc::default(),
+            members: Arc::default(),
+            ..self
+        };
+        match bot.check_discord_token().await {
+            Ok(_) => {
+                info!("Detected a bot token.");
+                Ok(bot)
+            }
+            Err(_) => Err(err),
+        }
+    }
+
+    /// The user the token belongs to, only fetched the first time.
+    pub async fn current_user(&self) -> Result<CurrentUser> {
+        match self.current_user.get() {
+            Some(user) => Ok(user.clone()),
+            None => self.check_discord_token().await,
+        }
+    }
+
+    /// Fetches a single page of up to [`GUILDS_PAGE_LIMIT`] guilds, starting
+    /// after the guild with ID `after`.
+    async fn get_guilds_page(&self, after: Option<Snowflake>) -> Result<Vec<Guild>> {
+        let mut query = vec![
+            ("limit", GUILDS_PAGE_LIMIT.to_string()),
+            ("with_counts", "true".to_string()),
+        ];
+        if let Some(after) = after {
+            query.push(("after", after.to_string()));
+        }
+
+        let response = self
+            .send_with(Method::GET, "/users/@me/guilds", |request| {
+                request.query(&query)
+            })
+            .await?;
+
+        Self::json(response).await
+    }
+
+    /// Streams every guild the current user is in, fetching further pages
+    /// only as the stream is consumed.
+    pub fn guilds(&self) -> impl Stream<Item = Result<Guild>> + '_ {
+        stream::try_unfold(
+            Some(None),
+            move |cursor: Option<Option<Snowflake>>| async move {
+                let Some(after) = cursor else {
+                    return Ok(None);
+                };
+
+                let page = self.get_guilds_page(after).await?;
+                debug!("Fetched a page of {} guilds", page.len());
+                let next = match page.last() {
+                    Some(last) if page.len() >= GUILDS_PAGE_LIMIT => Some(Some(last.id)),
+                    _ => None,
+                };
+
+                Ok::<_, Error>(Some((stream::iter(page.into_iter().map(Ok)), next)))
+            },
+        )
+        .try_flatten()
+    }
+
+    pub async fn get_guilds(&self) -> Result<Vec<Guild>> {
+        info!("Getting guilds...");
+
+        let guilds: Vec<Guild> = self.guilds().try_collect().await?;
+
+        info!("Successfully got {} guilds!", guilds.len());
+        Ok(guilds)
+    }
+
+    /// Estimates how long doing `operation` to `guilds` more guilds will
+    /// take under the current rate limits.
+    pub fn eta(&self, operation: Operation, guilds: usize) -> Option<Duration> {
+        let route = match operation {
+            Operation::LeaveGuild => Route::new(&Method::DELETE, "/users/@me/guilds/0"),
+            Operation::MuteGuild | Operation::UnmuteGuild => {
+                Route::new(&Method::PATCH, "/users/@me/guilds/0/settings")
+            }
+            Operation::TransferOwnership => Route::new(&Method::PATCH, "/guilds/0"),
+            Operation::DeleteGuild => Route::new(&Method::DELETE, "/guilds/0"),
+        };
+        self.ratelimiter
+            .estimate(&route, guilds.try_into().unwrap_or(u32::MAX))
+    }
+
+    /// Leaves a guild, refusing if the user owns it or it is on the allowlist.
+    pub async fn leave_guild(&self, guild: &Guild) -> Result<()> {
+        if let Some(reason) = self.allowlist.protects(guild) {
+            return Err(Error::ProtectedGuild {
+                id: guild.id,
+                name: guild.name.clone(),
+                reason,
+            });
+        }
+
+        self.leave_protected_guild(guild).await
+    }
+
+    /// Leaves a guild even if it is on the allowlist. Callers must have had
+    /// the user confirm by typing the guild's name first.
+    pub async fn leave_protected_guild(&self, guild: &Guild) -> Result<()> {
+        if guild.owner {
+            return Err(Error::OwnedGuild {
+                id: guild.id,
+                name: guild.name.clone(),
+            });
+        }
+
+        info!("Leaving guild {}...", guild.id);
+
+        let path = format!("/users/@me/guilds/{}", guild.id);
+        let result = self.send(Method::DELETE, &path).await;
+        self.record(Operation::LeaveGuild, guild, &result);
+        let response = result?;
+        if response.status() != StatusCode::NO_CONTENT {
+            return Err(Error::UnexpectedStatus(response.status()));
+        }
+
+        if !self.dry_run {
+            info!("Successfully left guild {}!", guild.id);
+        }
+        Ok(())
+    }
+
+    /// Mutes a guild along with its `@everyone` and role mentions, until
+    /// `until` or indefinitely. Only user accounts have notification
+    /// settings.
+    pub async fn mute_guild(&self, guild: &Guild, until: Option<DateTime<Utc>>) -> Result<()> {
+        info!("Muting guild {}...", guild.id);
+        self.update_notification_settings(
+            Operation::MuteGuild,
+            guild,
+            &NotificationSettings::mute(until),
+        )
+        .await?;
+
+        if !self.dry_run {
+            info!("Successfully muted guild {}!", guild.id);
+        }
+        Ok(())
+    }
+
+    /// Undoes [`DiscordClient::mute_guild`].
+    pub async fn unmute_guild(&self, guild: &Guild) -> Result<()> {
+        info!("Unmuting guild {}...", guild.id);
+        self.update_notification_settings(
+            Operation::UnmuteGuild,
+            guild,
+            &NotificationSettings::unmute(),
+        )
+        .await?;
+
+        if !self.dry_run {
+            info!("Successfully unmuted guild {}!", guild.id);
+        }
+        Ok(())
+    }
+
+    async fn update_notification_settings(
+        &self,
+        operation: Operation,
+        guild: &Guild,
+        settings: &NotificationSettings,
+    ) -> Result<()> {
+        let path = format!("/users/@me/guilds/{}/settings", guild.id);
+        let result = self
+            .send_with(Method::PATCH, &path, |request| request.json(settings))
+            .await;
+        self.record(operation, guild, &result);
+        result?;
+        Ok(())
+    }
+
+    /// Fetches up to 1000 members of a guild, enough to pick a new owner from.
+    pub async fn get_guild_members(&self, guild_id: Snowflake) -> Result<Vec<Member>> {
+        let path = format!("/guilds/{}/members", guild_id);
+        let response = self
+            .send_with(Method::GET, &path, |request| {
+                request.query(&[("limit", "1000")])
+            })
+            .await?;
+
+        Self::json(response).await
+    }
+
+    /// Fetches the full guild object, which says who owns it.
+    pub async fn get_guild(&self, guild_id: Snowflake) -> Result<GuildDetails> {
+        let path = format!("/guilds/{}", guild_id);
+        let response = self.send(Method::GET, &path).await?;
+
+        Self::json(response).await
+    }
+
+    /// Fetches the channels of a guild the current user can see.
+    pub async fn get_guild_channels(&self, guild_id: Snowflake) -> Result<Vec<Channel>> {
+        let path = format!("/guilds/{}/channels", guild_id);
+        let response = self.send(Method::GET, &path).await?;
+
+        Self::json(response).await
+    }
+
+    /// The current user's member object in a guild, only fetched the first
+    /// time. Bots can't use `/users/@me/guilds/{id}/member`, so they look
+    /// themselves up among the guild's members instead.
+    pub async fn current_member(&self, guild_id: Snowflake) -> Result<Member> {
+        if let Some(member) = self.members.lock().unwrap().get(&guild_id) {
+            return Ok(member.clone());
+        }
+
+        let path = if self.is_bot() {
+            let user = self.current_user().await?;
+            format!("/guilds/{}/members/{}", guild_id, user.id)
+        } else {
+            format!("/users/@me/guilds/{}/member", guild_id)
+        };
+        let response = self.send(Method::GET, &path).await?;
+        let member: Member = Self::json(response).await?;
+
+        self.members
+            .lock()
+            .unwrap()
+            .insert(guild_id, member.clone());
+        Ok(member)
+    }
+
+    /// Hands a guild the current user owns over to another member.
+    ///
+    /// `mfa_code` is the six-digit TOTP code, needed when the account has
+    /// two-factor authentication enabled.
+    pub async fn transfer_ownership(
+        &self,
+        guild: &Guild,
+        user_id: Snowflake,
+        mfa_code: Option<&str>,
+    ) -> Result<()> {
+        info!(
+            "Transferring ownership of guild {} to {}...",
+            guild.id, user_id
+        );
+
+        let mut body = serde_json::json!({ "owner_id": user_id });
+        if let Some(code) = mfa_code {
+            body["code"] = code.into();
+        }
+
+        let path = format!("/guilds/{}", guild.id);
+        let result = self
+            .send_with(Method::PATCH, &path, |request| request.json(&body))
+            .await;
+        self.record(Operation::TransferOwnership, guild, &result);
+        result?;
+
+        if !self.dry_run {
+            info!("Successfully transferred guild {}!", guild.id);
+        }
+        Ok(())
+    }
+
+    /// Permanently deletes a guild the current user owns.
+    pub async fn delete_guild(&self, guild: &Guild, mfa_code: Option<&str>) -> Result<()> {
+        info!("Deleting guild {}...", guild.id);
+
+        let path = format!("/guilds/{}", guild.id);
+        let result = self
+            .send_with(Method::DELETE, &path, |request| match mfa_code {
+                Some(code) => request.json(&serde_json::json!({ "code": code })),
+                None => request,
+            })
+            .await;
+        self.record(Operation::DeleteGuild, guild, &result);
+        result?;
+
+        if !self.dry_run {
+            info!("Successfully deleted guild {}!", guild.id);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1099 @@
+use std::future::Future;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt};
+
+use regex::RegexBuilder;
+use serde::Serialize;
+use tracing::{error, info, warn};
+
+use crate::cli::{AllowlistCommand, Exit, GuildSort, OutputFormat, VaultCommand};
+use crate::client::DiscordClient;
+use crate::config::Config;
+use crate::error::{Result, MFA_REQUIRED};
+use crate::export::{self, Column};
+use crate::filter::Filter;
+use crate::guild::{Activity, Guild};
+use crate::journal::{Operation, Outcome, Plan, PlannedGuild, RunLog};
+use crate::owned;
+use crate::progress::Progress;
+use crate::prompt;
+use crate::prune::{self, Criteria};
+use crate::snowflake::Snowflake;
+use crate::token::Token;
+use crate::vault::Vault;
+
+pub async fn whoami(client: &DiscordClient, output: OutputFormat) -> Result<Exit> {
+    let user = client.current_user().await?;
+
+    match output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&user)?),
+        OutputFormat::Ndjson => println!("{}", serde_json::to_string(&user)?),
+        _ => {
+            let yes_no = |value: bool| if value { "yes" } else { "no" };
+            println!("{}", user);
+            println!("  ID:        {}", user.id);
+            if user.bot {
+                // The rest describes a person's account and isn't sent for bots.
+                println!("  Bot:       yes");
+                println!("  Flags:     {:#x}", user.flags);
+                return Ok(Exit::Success);
+            }
+            println!(
+                "  Locale:    {}",
+                user.locale.as_deref().unwrap_or("unknown")
+            );
+            println!("  Verified:  {}", user.verified.map_or("unknown", yes_no));
+            println!("  2FA:       {}", yes_no(user.mfa_enabled));
+            println!("  Nitro:     {}", user.premium());
+            println!("  Flags:     {:#x}", user.flags);
+        }
+    }
+    Ok(Exit::Success)
+}
+
+/// Fills in [`Guild::member`] for every guild. Guilds whose member object
+/// can't be fetched are left without one.
+pub async fn fetch_members(client: &DiscordClient, guilds: &mut [Guild]) {
+    info!("Getting join dates...");
+    fill_in(
+        client,
+        guilds,
+        "Inspecting",
+        "your membership",
+        |id| client.current_member(id),
+        |guild, member| guild.member = Some(member),
+    )
+    .await;
+}
+
+/// Fills in [`Guild::activity`] for every guild from its channels. Guilds
+/// whose channels can't be fetched are left without it.
+pub async fn fetch_activity(client: &DiscordClient, guilds: &mut [Guild]) {
+    info!("Getting channel activity...");
+    fill_in(
+        client,
+        guilds,
+        "Scanning",
+        "the channels",
+        |id| client.get_guild_channels(id),
+        |guild, channels| guild.activity = Some(Activity::from_channels(&channels)),
+    )
+    .await;
+}
+
+/// Runs `fetch` for every guild, [`DiscordClient::concurrency`] at a time,
+/// and hands each result to `apply`. `label` heads the progress bar, and
+/// `what` names what was fetched in the warning when a fetch fails.
+async fn fill_in<T, F, Fut>(
+    client: &DiscordClient,
+    guilds: &mut [Guild],
+    label: &'static str,
+    what: &str,
+    fetch: F,
+    apply: impl Fn(&mut Guild, T),
+) where
+    F: Fn(Snowflake) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut progress = Progress::new(label, guilds.len());
+    let mut fetched = stream::iter(guilds.iter_mut())
+        .map(|guild| {
+            let result = fetch(guild.id);
+            async { (guild, result.await) }
+        })
+        .buffer_unordered(client.concurrency());
+
+    while let Some((guild, result)) = fetched.next().await {
+        match result {
+            Ok(value) => {
+                apply(guild, value);
+                progress.succeeded();
+            }
+            Err(e) => {
+                warn!("Couldn't get {} of {}: {}", what, guild.name, e);
+                progress.failed();
+            }
+        }
+        progress.draw(None);
+    }
+    progress.finish();
+}
+
+#[derive(Debug, Serialize)]
+struct ActivityReport<'a> {
+    id: Snowflake,
+    name: &'a str,
+    last_message_at: Option<DateTime<Utc>>,
+    inactive_days: Option<i64>,
+    /// Quiet for long enough and not kept, so `--leave` would leave it.
+    inactive: bool,
+    /// Owned or protected, so `--leave` skips it however quiet it is.
+    kept: bool,
+}
+
+/// Ranks guilds by how long it has been since anyone posted in them, most
+/// dead first, flagging the ones quiet for over `inactive_days` that could be
+/// left. With `leave`, leaves the flagged guilds instead of reporting on all
+/// of them.
+pub async fn guild_activity(
+    client: &DiscordClient,
+    inactive_days: u32,
+    leave: bool,
+    yes: bool,
+    output: OutputFormat,
+) -> Result<Exit> {
+    let mut guilds = client.get_guilds().await?;
+    fetch_activity(client, &mut guilds).await;
+    // Unknown activity sorts last, since it can't be called dead.
+    guilds.sort_by_key(|guild| (guild.last_active_at().is_none(), guild.last_active_at()));
+
+    let now = Utc::now();
+    let days = |guild: &Guild| guild.last_active_at().map(|at| (now - at).num_days());
+    let is_quiet = |guild: &Guild| days(guild).is_some_and(|days| days > inactive_days.into());
+    let is_kept = |guild: &Guild| guild.owner || client.allowlist().protects(guild).is_some();
+
+    if leave {
+        let dead: Vec<Snowflake> = guilds
+            .iter()
+            .filter(|guild| is_quiet(guild) && can_leave(client, guild, false))
+            .map(|guild| guild.id)
+            .collect();
+        if dead.is_empty() {
+            println!(
+                "No guilds that can be left have been inactive for over {} days.",
+                inactive_days
+            );
+            return Ok(Exit::Success);
+        }
+        return run_on_ids(
+            client,
+            Action::Leave { force: false },
+            &guilds,
+            dead,
+            yes,
+            output,
+        )
+        .await;
+    }
+
+    let report: Vec<_> = guilds
+        .iter()
+        .map(|guild| ActivityReport {
+            id: guild.id,
+            name: &guild.name,
+            last_message_at: guild.activity.as_ref().and_then(|a| a.last_message_at),
+            inactive_days: days(guild),
+            inactive: is_quiet(guild) && !is_kept(guild),
+            kept: is_kept(guild),
+        })
+        .collect();
+
+    match output {
+        format if format.is_json() => export::print_json(&report, format)?,
+        _ => {
+            for (guild, entry) in guilds.iter().zip(&report) {
+                let last = match (&guild.activity, entry.last_message_at) {
+                    (None, _) => "unknown".to_string(),
+                    (Some(_), None) => "never".to_string(),
+                    (Some(_), Some(at)) => at.format("%Y-%m-%d").to_string(),
+                };
+                let days = entry
+                    .inactive_days
+                    .map_or_else(|| "?".to_string(), |days| days.to_string());
+                println!(
+                    "{} {:>6}d  {:<10}  {}  {}",
+                    match (entry.inactive, entry.kept && is_quiet(guild)) {
+                        (true, _) => "!",
+                        (false, true) => "-",
+                        (false, false) => " ",
+                    },
+                    days,
+                    last,
+                    guild.id,
+                    guild
+                );
+            }
+            let dead = report.iter().filter(|entry| entry.inactive).count();
+            println!(
+                "{} of {} guild(s) have been inactive for over {} days. Leave them with `guilds activity --leave`.",
+                dead,
+                report.len(),
+                inactive_days
+            );
+            let kept = guilds
+                .iter()
+                .zip(&report)
+                .filter(|(guild, entry)| entry.kept && is_quiet(guild))
+                .count();
+            if kept > 0 {
+                println!(
+                    "{} more marked - are just as quiet, but owned or protected, so they are never left.",
+                    kept
+                );
+            }
+        }
+    }
+    Ok(Exit::Success)
+}
+
+pub async fn list_guilds(
+    client: &DiscordClient,
+    filter: Option<&Filter>,
+    sort: Option<GuildSort>,
+    columns: &[Column],
+    output: OutputFormat,
+) -> Result<Exit> {
+    let mut guilds = client.get_guilds().await?;
+    if filter.is_some_and(Filter::needs_members)
+        || sort == Some(GuildSort::Joined)
+        || (output != OutputFormat::Text && columns.contains(&Column::Joined))
+    {
+        fetch_members(client, &mut guilds).await;
+    }
+    if filter.is_some_and(Filter::needs_activity)
+        || (output != OutputFormat::Text && columns.contains(&Column::LastMessage))
+    {
+        fetch_activity(client, &mut guilds).await;
+    }
+    if let Some(filter) = filter {
+        guilds.retain(|guild| filter.matches(guild));
+    }
+    match sort {
+        Some(GuildSort::Name) => guilds.sort_by_key(|guild| guild.name.to_lowercase()),
+        Some(GuildSort::Members) => guilds.sort_by_key(|guild| guild.approximate_member_count),
+        Some(GuildSort::Created) => guilds.sort_by_key(|guild| guild.id),
+        // Oldest first, with unknown join dates at the end.
+        Some(GuildSort::Joined) => {
+            guilds.sort_by_key(|guild| (guild.joined_at().is_none(), guild.joined_at()))
+        }
+        None => {}
+    }
+
+    match output {
+        OutputFormat::Text => {
+            for guild in &guilds {
+                println!("{}  {}", guild.id, guild);
+            }
+        }
+        format => export::write_guilds(&mut io::stdout().lock(), &guilds, format, columns)?,
+    }
+    Ok(Exit::Success)
+}
+
+/// What a bulk run does to each of its guilds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// With `force`, also leaves guilds on the allowlist once the user has
+    /// typed each one's name.
+    Leave {
+        force: bool,
+    },
+    Mute {
+        until: Option<DateTime<Utc>>,
+    },
+    Unmute,
+}
+
+impl Action {
+    /// The action a journaled run was started with, or `None` for
+    /// operations that aren't run in bulk.
+    fn from_plan(plan: &Plan, force: bool) -> Option<Self> {
+        match plan.operation {
+            Operation::LeaveGuild => Some(Action::Leave { force }),
+            Operation::MuteGuild => Some(Action::Mute {
+                until: plan.mute_until,
+            }),
+            Operation::UnmuteGuild => Some(Action::Unmute),
+            Operation::TransferOwnership | Operation::DeleteGuild => None,
+        }
+    }
+
+    pub fn operation(self) -> Operation {
+        match self {
+            Action::Leave { .. } => Operation::LeaveGuild,
+            Action::Mute { .. } => Operation::MuteGuild,
+            Action::Unmute => Operation::UnmuteGuild,
+        }
+    }
+
+    /// A new run's plan for doing this to `guilds`.
+    pub fn plan(self, dry_run: bool, confirmed: bool, guilds: Vec<PlannedGuild>) -> Plan {
+        let mut plan = Plan::new(self.operation(), dry_run, confirmed, guilds);
+        if let Action::Mute { until } = self {
+            plan.mute_until = until;
+        }
+        plan
+    }
+
+    pub fn verb(self) -> &'static str {
+        match self {
+            Action::Leave { .. } => "leave",
+            Action::Mute { .. } => "mute",
+            Action::Unmute => "unmute",
+        }
+    }
+
+    pub fn past_tense(self) -> &'static str {
+        match self {
+            Action::Leave { .. } => "left",
+            Action::Mute { .. } => "muted",
+            Action::Unmute => "unmuted",
+        }
+    }
+
+    /// The verb for the progress line.
+    pub fn progressive(self) -> &'static str {
+        match self {
+            Action::Leave { .. } => "Leaving",
+            Action::Mute { .. } => "Muting",
+            Action::Unmute => "Unmuting",
+        }
+    }
+
+    async fn apply(self, client: &DiscordClient, guild: &Guild) -> Result<()> {
+        match self {
+            Action::Leave { force: true } if client.allowlist().protects(guild).is_some() => {
+                client.leave_protected_guild(guild).await
+            }
+            Action::Leave { .. } => client.leave_guild(guild).await,
+            Action::Mute { until } => client.mute_guild(guild, until).await,
+            Action::Unmute => client.unmute_guild(guild).await,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct GuildOutcome {
+    id: Snowflake,
+    name: Option<String>,
+    operation: Operation,
+    done: bool,
+    /// The same as `done` for leave runs, which is all scripts written
+    /// before muting existed look at.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    left: Option<bool>,
+    dry_run: bool,
+    error: Option<String>,
+}
+
+/// Fetches the guilds along with whatever `filter` needs to know about
+/// them, and picks `ids` plus every guild matching `filter` that
+/// `eligible` lets through.
+async fn select_guilds(
+    client: &DiscordClient,
+    ids: &[Snowflake],
+    filter: Option<&Filter>,
+    eligible: impl Fn(&Guild) -> bool,
+) -> Result<(Vec<Guild>, Vec<Snowflake>)> {
+    let mut guilds = client.get_guilds().await?;
+    if filter.is_some_and(Filter::needs_members) {
+        fetch_members(client, &mut guilds).await;
+    }
+    if filter.is_some_and(Filter::needs_activity) {
+        fetch_activity(client, &mut guilds).await;
+    }
+
+    let mut ids = ids.to_vec();
+    if let Some(filter) = filter {
+        for guild in guilds.iter().filter(|guild| filter.matches(guild)) {
+            if eligible(guild) && !ids.contains(&guild.id) {
+                ids.push(guild.id);
+            }
+        }
+    }
+    Ok((guilds, ids))
+}
+
+pub async fn leave_guilds(
+    client: &DiscordClient,
+    ids: &[Snowflake],
+    filter: Option<&Filter>,
+    force: bool,
+    yes: bool,
+    output: OutputFormat,
+) -> Result<Exit> {
+    let (guilds, ids) =
+        select_guilds(client, ids, filter, |guild| can_leave(client, guild, force)).await?;
+    if ids.is_empty() {
+        println!("No guilds matched.");
+        return Ok(Exit::Success);
+    }
+
+    run_on_ids(client, Action::Leave { force }, &guilds, ids, yes, output).await
+}
+
+/// Mutes or unmutes the guilds in `ids` and those matching `filter`. Unlike
+/// leaving, this is fine for owned and allowlisted guilds too.
+pub async fn mute_guilds(
+    client: &DiscordClient,
+    action: Action,
+    ids: &[Snowflake],
+    filter: Option<&Filter>,
+    yes: bool,
+    output: OutputFormat,
+) -> Result<Exit> {
+    if client.is_bot() {
+        error!("Bots have no notification settings to {}.", action.verb());
+        return Ok(Exit::Failure);
+    }
+
+    let (guilds, ids) = select_guilds(client, ids, filter, |_| true).await?;
+    if ids.is_empty() {
+        println!("No guilds matched.");
+        return Ok(Exit::Success);
+    }
+
+    run_on_ids(client, action, &guilds, ids, yes, output).await
+}
+
+/// Whether a guild picked by a filter or an analysis may be left, warning
+/// about it if not.
+fn can_leave(client: &DiscordClient, guild: &Guild, force: bool) -> bool {
+    if guild.owner {
+        warn!(
+            "Skipping {} ({}): you own it, so it can't be left. Transfer or delete it with `guilds transfer` or `guilds delete` instead.",
+            guild.name, guild.id
+        );
+        return false;
+    }
+    if let Some(reason) = client.allowlist().protects(guild).filter(|_| !force) {
+        warn!("Skipping {} ({}): {}.", guild.name, guild.id, reason);
+        return false;
+    }
+    true
+}
+
+/// Confirms and journals doing `action` to `ids`, then does it.
+async fn run_on_ids(
+    client: &DiscordClient,
+    action: Action,
+    guilds: &[Guild],
+    ids: Vec<Snowflake>,
+    yes: bool,
+    output: OutputFormat,
+) -> Result<Exit> {
+    let find = |id: Snowflake| guilds.iter().find(|guild| guild.id == id);
+
+    if !yes {
+        let mut console = console(output);
+        writeln!(
+            console,
+            "About to {} {} guild(s):",
+            action.verb(),
+            ids.len()
+        )?;
+        for &id in &ids {
+            match find(id) {
+                Some(guild) => writeln!(console, "  {}  {}", id, guild)?,
+                None => writeln!(console, "  {}", id)?,
+            }
+        }
+        if let Action::Mute { until: Some(until) } = action {
+            writeln!(
+                console,
+                "They will be unmuted again on {}.",
+                until.format("%Y-%m-%d %H:%M UTC")
+            )?;
+        }
+        if !prompt::confirm_to(&mut console, "Continue")? {
+            return Ok(Exit::Aborted);
+        }
+    }
+
+    let plan = action.plan(
+        client.is_dry_run(),
+        true,
+        ids.iter()
+            .map(|&id| PlannedGuild {
+                id,
+                name: find(id).map(|guild| guild.name.clone()),
+            })
+            .collect(),
+    );
+    let mut log = RunLog::start(client.journal(), &plan);
+    let pending = plan.guilds.iter().enumerate().collect();
+    run_planned(client, action, guilds, pending, &mut log, None, output).await
+}
+
+/// Leaves the guilds that meet the prune criteria, at most `limit` of them.
+/// The preview is printed even with `--yes`, on stderr when the output is
+/// meant for another program.
+pub async fn prune_guilds(
+    client: &DiscordClient,
+    criteria: &Criteria,
+    limit: usize,
+    yes: bool,
+    output: OutputFormat,
+) -> Result<Exit> {
+    let guilds = client.get_guilds().await?;
+
+    let (kept, leavable): (Vec<_>, Vec<_>) = guilds
+        .iter()
+        .cloned()
+        .partition(|guild| guild.owner || client.allowlist().protects(guild).is_some());
+    if !kept.is_empty() {
+        info!(
+            "Not checking {} guild(s) that are owned or on the allowlist.",
+            kept.len()
+        );
+    }
+
+    let candidates = prune::candidates(client, &leavable, criteria).await?;
+    if candidates.is_empty() {
+        println!("No guilds need pruning.");
+        return Ok(Exit::Success);
+    }
+    let over_limit = candidates.len().saturating_sub(limit);
+    let candidates = &candidates[..candidates.len() - over_limit];
+
+    let mut preview = console(output);
+    writeln!(preview, "About to leave {} guild(s):", candidates.len())?;
+    for candidate in candidates {
+        let reasons: Vec<_> = candidate.reasons.iter().map(ToString::to_string).collect();
+        writeln!(
+            preview,
+            "  {}  {}: {}",
+            candidate.guild.id,
+            candidate.guild,
+            reasons.join("; ")
+        )?;
+    }
+    if over_limit > 0 {
+        writeln!(
+            preview,
+            "{} more guild(s) matched but are over the limit of {}. Run prune again to leave them.",
+            over_limit, limit
+        )?;
+    }
+    if !yes && !prompt::confirm_to(&mut preview, "Continue")? {
+        return Ok(Exit::Aborted);
+    }
+
+    let action = Action::Leave { force: false };
+    let plan = action.plan(
+        client.is_dry_run(),
+        true,
+        candidates
+            .iter()
+            .map(|candidate| PlannedGuild {
+                id: candidate.guild.id,
+                name: Some(candidate.guild.name.clone()),
+            })
+            .collect(),
+    );
+    let mut log = RunLog::start(client.journal(), &plan);
+    let pending = plan.guilds.iter().enumerate().collect();
+    run_planned(client, action, &guilds, pending, &mut log, None, output).await
+}
+
+/// Continues a journaled run: deals with the guilds it never got to and
+/// retries the ones that failed.
+pub async fn resume(
+    client: &DiscordClient,
+    run_id: &str,
+    force: bool,
+    yes: bool,
+    output: OutputFormat,
+) -> Result<Exit> {
+    let Some(journal) = client.journal() else {
+        error!("There is no journal to resume runs from.");
+        return Ok(Exit::Failure);
+    };
+    let Some(run) = journal.load_run(run_id)? else {
+        error!(
+            "There is no run {} in the journal at {}.",
+            run_id,
+            journal.path().display()
+        );
+        return Ok(Exit::Failure);
+    };
+    let Some(action) = Action::from_plan(&run.plan, force) else {
+        error!("Run {} isn't a bulk run, so it can't be resumed.", run_id);
+        return Ok(Exit::Failure);
+    };
+    if let Action::Mute { until: Some(until) } = action {
+        if until <= Utc::now() {
+            println!(
+                "Run {}'s mutes would have ended on {} anyway.",
+                run_id,
+                until.format("%Y-%m-%d %H:%M UTC")
+            );
+            return Ok(Exit::Success);
+        }
+    }
+
+    let pending = run.pending(client.is_dry_run());
+    if pending.is_empty() {
+        println!("Run {} is already complete.", run_id);
+        return Ok(Exit::Success);
+    }
+
+    let retries = pending.iter().filter(|(i, _)| *i < run.cursor()).count();
+    // Runs that asked about each guild keep doing so for the guilds they
+    // never reached; everything else was agreed to already.
+    let ask_from = (!run.plan.confirmed).then_some(run.cursor());
+    if !yes && run.plan.confirmed {
+        let mut console = console(output);
+        writeln!(
+            console,
+            "Run {} has {} guild(s) left to {} and {} to retry:",
+            run_id,
+            pending.len() - retries,
+            action.verb(),
+            retries
+        )?;
+        for (_, guild) in &pending {
+            match &guild.name {
+                Some(name) => writeln!(console, "  {}  {}", guild.id, name)?,
+                None => writeln!(console, "  {}", guild.id)?,
+            }
+        }
+        if !prompt::confirm_to(&mut console, "Continue")? {
+            return Ok(Exit::Aborted);
+        }
+    }
+
+    let guilds = client.get_guilds().await?;
+    let mut log = RunLog::resume(client.journal(), &run, client.is_dry_run());
+    run_planned(client, action, &guilds, pending, &mut log, ask_from, output).await
+}
+
+/// Does `action` to each pending guild of a run through a pool of
+/// [`DiscordClient::concurrency`] workers, journaling its progress. Guilds
+/// from `ask_from` onwards in the plan are only acted on if the user says
+/// so, and every question is asked before the first request is sent.
+pub async fn run_planned(
+    client: &DiscordClient,
+    action: Action,
+    guilds: &[Guild],
+    pending: Vec<(usize, &PlannedGuild)>,
+    log: &mut RunLog<'_>,
+    ask_from: Option<usize>,
+    output: OutputFormat,
+) -> Result<Exit> {
+    let mut console = console(output);
+    let mut chosen = Vec::with_capacity(pending.len());
+    for (index, planned) in pending {
+        let guild = guilds.iter().find(|guild| guild.id == planned.id);
+        let target = match guild {
+            Some(guild) if ask_from.is_some_and(|from| index >= from) => {
+                let question = format!("{} guild {}", capitalize(action.verb()), guild);
+                if !prompt::confirm_to(&mut console, &question)? {
+                    log.record(index, planned.id, Outcome::Skipped);
+                    continue;
+                }
+                Ok(guild)
+            }
+            Some(guild)
+                if action == (Action::Leave { force: true })
+                    && client.allowlist().protects(guild).is_some() =>
+            {
+                if confirm_protected(&mut console, guild)? {
+                    Ok(guild)
+                } else {
+                    Err("the typed name didn't match".to_string())
+                }
+            }
+            Some(guild) => Ok(guild),
+            None => Err("not a member of this guild".to_string()),
+        };
+        chosen.push((index, planned, target));
+    }
+
+    let operation = action.operation();
+    let mut progress = Progress::new(action.progressive(), chosen.len());
+    let mut results = stream::iter(chosen)
+        .map(|(index, planned, target)| async move {
+            let result = match &target {
+                Ok(guild) => action.apply(client, guild).await.map_err(|e| e.to_string()),
+                Err(e) => Err(e.clone()),
+            };
+            (index, planned, target.ok(), result)
+        })
+        .buffer_unordered(client.concurrency());
+
+    let mut outcomes = Vec::new();
+    let mut ticker = tokio::time::interval(Duration::from_millis(250));
+    loop {
+        let (index, planned, guild, result) = tokio::select! {
+            next = results.next() => match next {
+                Some(next) => next,
+                None => break,
+            },
+            _ = ticker.tick() => {
+                progress.draw(client.eta(operation, progress.remaining()));
+                continue;
+            }
+        };
+
+        if let Err(e) = &result {
+            error!("Failed to {} guild {}: {}", action.verb(), planned.id, e);
+            progress.failed();
+        } else {
+            progress.succeeded();
+        }
+        progress.draw(client.eta(operation, progress.remaining()));
+        log.record(
+            index,
+            planned.id,
+            if result.is_ok() {
+                Outcome::Done
+            } else {
+                Outcome::Failed
+            },
+        );
+        outcomes.push((
+            index,
+            GuildOutcome {
+                id: planned.id,
+                name: guild
+                    .map(|guild| guild.name.clone())
+                    .or_else(|| planned.name.clone()),
+                operation,
+                done: result.is_ok(),
+                left: matches!(action, Action::Leave { .. }).then_some(result.is_ok()),
+                dry_run: client.is_dry_run(),
+                error: result.err(),
+            },
+        ));
+    }
+    progress.finish();
+
+    outcomes.sort_by_key(|(index, _)| *index);
+    let outcomes: Vec<_> = outcomes.into_iter().map(|(_, outcome)| outcome).collect();
+
+    match output {
+        format if format.is_json() => export::print_json(&outcomes, format)?,
+        _ => {
+            for outcome in &outcomes {
+                let name = outcome
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| outcome.id.to_string());
+                match &outcome.error {
+                    None if outcome.dry_run => {
+                        println!("Would have {} guild {}.", action.past_tense(), name)
+                    }
+                    None => println!("Successfully {} guild {}!", action.past_tense(), name),
+                    Some(e) => println!("Failed to {} guild {}: {}", action.verb(), name, e),
+                }
+            }
+            let done = outcomes.iter().filter(|outcome| outcome.done).count();
+            println!(
+                "{}",
+                summary(client, action, done, outcomes.len() - done, outcomes.len())
+            );
+        }
+    }
+
+    if outcomes.iter().all(|outcome| outcome.done) {
+        Ok(Exit::Success)
+    } else {
+        Ok(Exit::Partial)
+    }
+}
+
+/// Makes the user type a protected guild's name before `--force` may leave
+/// it. Deliberately ignores `--yes`.
+fn confirm_protected(console: &mut dyn Write, guild: &Guild) -> Result<bool> {
+    writeln!(
+        console,
+        "{} is on the allowlist. Type its name to leave it anyway:",
+        guild.name
+    )?;
+    console.flush()?;
+    Ok(prompt::read_line()?.trim() == guild.name)
+}
+
+/// Where previews and questions go: stdout, unless it is reserved for
+/// machine-readable output.
+fn console(output: OutputFormat) -> Box<dyn Write> {
+    if output.is_json() {
+        Box::new(io::stderr())
+    } else {
+        Box::new(io::stdout())
+    }
+}
+
+pub async fn transfer_guild(
+    client: &DiscordClient,
+    guild_id: Snowflake,
+    user_id: Snowflake,
+    mfa_code: Option<&str>,
+    yes: bool,
+) -> Result<Exit> {
+    let Some(guild) = find_owned_guild(client, guild_id).await? else {
+        return Ok(Exit::Failure);
+    };
+    owned::warn_without_mfa(&client.current_user().await?);
+
+    if !yes
+        && !prompt::confirm(&format!(
+            "Transfer ownership of {} to user {}? You will no longer own it",
+            guild.name, user_id
+        ))?
+    {
+        return Ok(Exit::Aborted);
+    }
+
+    let result = client.transfer_ownership(&guild, user_id, mfa_code).await;
+    mfa_hint(result)?;
+    println!(
+        "{}",
+        owned::transferred(client, &guild, format!("user {}", user_id))
+    );
+    Ok(Exit::Success)
+}
+
+pub async fn delete_guild(
+    client: &DiscordClient,
+    guild_id: Snowflake,
+    mfa_code: Option<&str>,
+    yes: bool,
+) -> Result<Exit> {
+    let Some(guild) = find_owned_guild(client, guild_id).await? else {
+        return Ok(Exit::Failure);
+    };
+    owned::warn_without_mfa(&client.current_user().await?);
+
+    if !yes
+        && !prompt::confirm(&format!(
+            "Permanently delete {}? This cannot be undone",
+            guild.name
+        ))?
+    {
+        return Ok(Exit::Aborted);
+    }
+
+    let result = client.delete_guild(&guild, mfa_code).await;
+    mfa_hint(result)?;
+    println!("{}", owned::deleted(client, &guild));
+    Ok(Exit::Success)
+}
+
+/// Looks up a guild the current user owns, logging why if it isn't one.
+async fn find_owned_guild(client: &DiscordClient, guild_id: Snowflake) -> Result<Option<Guild>> {
+    let guild = client
+        .get_guilds()
+        .await?
+        .into_iter()
+        .find(|guild| guild.id == guild_id);
+
+    match guild {
+        Some(guild) if guild.owner => Ok(Some(guild)),
+        Some(guild) => {
+            error!("You don't own {} ({}).", guild.name, guild.id);
+            Ok(None)
+        }
+        None => {
+            error!("You aren't a member of guild {}.", guild_id);
+            Ok(None)
+        }
+    }
+}
+
+/// Points the user at `--mfa-code` when Discord wants two-factor
+/// authentication for an owner-only action.
+fn mfa_hint(result: Result<()>) -> Result<()> {
+    if let Err(e) = &result {
+        if e.code() == Some(MFA_REQUIRED) {
+            error!(
+                "This action requires two-factor authentication. Rerun it with --mfa-code <CODE>."
+            );
+        }
+    }
+    result
+}
+
+/// The end-of-run line for a bulk run, which says loudly when nothing
+/// actually happened.
+pub fn summary(
+    client: &DiscordClient,
+    action: Action,
+    done: usize,
+    failed: usize,
+    total: usize,
+) -> String {
+    if client.is_dry_run() {
+        format!(
+            "DRY RUN: would have {} {} of {} guild(s), {} would fail. Nothing was changed.",
+            action.past_tense(),
+            done,
+            total,
+            failed
+        )
+    } else {
+        format!(
+            "{} {} of {} guild(s), {} failed.",
+            capitalize(action.past_tense()),
+            done,
+            total,
+            failed
+        )
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Serialize)]
+struct DecodedId {
+    id: Snowflake,
+    created_at: DateTime<Utc>,
+    worker_id: u8,
+    process_id: u8,
+    increment: u16,
+}
+
+pub fn decode(id: Snowflake, output: OutputFormat) -> Result<Exit> {
+    let decoded = DecodedId {
+        id,
+        created_at: id.created_at(),
+        worker_id: id.worker_id(),
+        process_id: id.process_id(),
+        increment: id.increment(),
+    };
+
+    match output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&decoded)?),
+        OutputFormat::Ndjson => println!("{}", serde_json::to_string(&decoded)?),
+        _ => {
+            println!("{}", decoded.id);
+            println!("  Created:   {}", decoded.created_at.to_rfc3339());
+            println!("  Worker:    {}", decoded.worker_id);
+            println!("  Process:   {}", decoded.process_id);
+            println!("  Increment: {}", decoded.increment);
+        }
+    }
+    Ok(Exit::Success)
+}
+
+pub fn allowlist(
+    config: &mut Config,
+    path: &Path,
+    command: &AllowlistCommand,
+    output: OutputFormat,
+) -> Result<Exit> {
+    let allowlist = &mut config.allowlist;
+
+    match command {
+        AllowlistCommand::Add(entries) => {
+            for pattern in &entries.patterns {
+                if let Err(e) = RegexBuilder::new(pattern).build() {
+                    error!("Invalid pattern /{}/: {}", pattern, e);
+                    return Ok(Exit::Failure);
+                }
+            }
+            for id in &entries.ids {
+                if !allowlist.ids.contains(id) {
+                    allowlist.ids.push(*id);
+                }
+                println!("Protected guild {}.", id);
+            }
+            for pattern in &entries.patterns {
+                if !allowlist.patterns.contains(pattern) {
+                    allowlist.patterns.push(pattern.clone());
+                }
+                println!("Protected guilds matching /{}/.", pattern);
+            }
+        }
+        AllowlistCommand::Remove(entries) => {
+            for id in &entries.ids {
+                match allowlist.ids.iter().position(|existing| existing == id) {
+                    Some(i) => {
+                        allowlist.ids.remove(i);
+                        println!("Guild {} is no longer protected.", id);
+                    }
+                    None => println!("Guild {} wasn't on the allowlist.", id),
+                }
+            }
+            for pattern in &entries.patterns {
+                match allowlist
+                    .patterns
+                    .iter()
+                    .position(|existing| existing == pattern)
+                {
+                    Some(i) => {
+                        allowlist.patterns.remove(i);
+                        println!("Guilds matching /{}/ are no longer protected.", pattern);
+                    }
+                    None => println!("/{}/ wasn't on the allowlist.", pattern),
+                }
+            }
+        }
+        AllowlistCommand::List => {
+            match output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(allowlist)?),
+                OutputFormat::Ndjson => println!("{}", serde_json::to_string(allowlist)?),
+                _ => {
+                    if allowlist.ids.is_empty() && allowlist.patterns.is_empty() {
+                        println!("The allowlist is empty.");
+                    }
+                    for id in &allowlist.ids {
+                        println!("{}", id);
+                    }
+                    for pattern in &allowlist.patterns {
+                        println!("/{}/", pattern);
+                    }
+                }
+            }
+            return Ok(Exit::Success);
+        }
+    }
+
+    config.save(path)?;
+    Ok(Exit::Success)
+}
+
+pub fn vault(
+    vault: &mut Vault,
+    command: &VaultCommand,
+    token: Option<Token>,
+    output: OutputFormat,
+) -> Result<Exit> {
+    match command {
+        VaultCommand::Add { name } => {
+            let Some(token) = token.filter(|token| !token.is_empty()) else {
+                error!("No token to add! Pass it with --token-stdin, --token-file or DISCORD_TOKEN, or type it when asked.");
+                return Ok(Exit::InvalidToken);
+            };
+            vault.insert(name, &token)?;
+            vault.save()?;
+            println!(
+                "Stored the token as {} in {}.",
+                name,
+                vault.path().display()
+            );
+        }
+        VaultCommand::Remove { name } => {
+            if vault.remove(name) {
+                vault.save()?;
+                println!("Removed the token {}.", name);
+            } else {
+                println!("There is no token named {}.", name);
+            }
+        }
+        VaultCommand::List => match output {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&vault.names())?),
+            OutputFormat::Ndjson => println!("{}", serde_json::to_string(&vault.names())?),
+            _ => {
+                if vault.is_empty() {
+                    println!("The vault is empty.");
+                }
+                for name in vault.names() {
+                    println!("{}", name);
+                }
+            }
+        },
+    }
+    Ok(Exit::Success)
+}
@@ -0,0 +1,84 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use regex::RegexBuilder;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::error::{Error, Result};
+use crate::guild::Guild;
+use crate::snowflake::Snowflake;
+
+/// Persistent settings, stored as JSON in the user's config directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub allowlist: Allowlist,
+}
+
+impl Config {
+    /// `<config dir>/discord-manager-rust/config.json`, e.g.
+    /// `~/.config/discord-manager-rust/config.json` on Linux.
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(config_dir()?.join("config.json"))
+    }
+
+    /// Loads the config, falling back to the defaults if the file doesn't exist yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| {
+                Error::Config(format!("{} is not a valid config: {}", path.display(), e))
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// The directory all of the tool's own files live in.
+pub fn config_dir() -> Result<PathBuf> {
+    dirs::config_dir()
+        .map(|dir| dir.join(env!("CARGO_PKG_NAME")))
+        .ok_or_else(|| io::Error::other("could not determine the config directory").into())
+}
+
+/// Guilds that must never be left, by ID or by a case-insensitive regex on
+/// their name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Allowlist {
+    #[serde(default)]
+    pub ids: Vec<Snowflake>,
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+impl Allowlist {
+    /// Returns why a guild is protected, or `None` if it isn't.
+    pub fn protects(&self, guild: &Guild) -> Option<String> {
+        if self.ids.contains(&guild.id) {
+            return Some(format!("ID {} is on the allowlist", guild.id));
+        }
+
+        self.patterns.iter().find_map(|pattern| {
+            match RegexBuilder::new(pattern).case_insensitive(true).build() {
+                Ok(regex) if regex.is_match(&guild.name) => {
+                    Some(format!("name matches allowlist pattern /{}/", pattern))
+                }
+                Ok(_) => None,
+                Err(e) => {
+                    warn!("Ignoring invalid allowlist pattern /{}/: {}", pattern, e);
+                    None
+                }
+            }
+        })
+    }
+}
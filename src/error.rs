@@ -0,0 +1,185 @@
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use crate::snowflake::Snowflake;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Discord's JSON error code for actions that need two-factor authentication.
+pub const MFA_REQUIRED: u64 = 60003;
+
+/// The JSON error body Discord sends alongside non-2xx responses.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiError {
+    pub code: u64,
+    pub message: String,
+    /// Nested per-field validation errors, kept as-is since their shape
+    /// mirrors whatever request body was sent.
+    #[serde(default)]
+    pub errors: Option<serde_json::Value>,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (code {})", self.message, self.code)?;
+        if let Some(errors) = &self.errors {
+            write!(f, ": {}", errors)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request never produced a response (DNS, TLS, connection reset, timeout...).
+    Transport(reqwest::Error),
+    /// A response arrived but its body was not what we expected.
+    Decode(serde_json::Error),
+    /// Discord answered with a 4xx status.
+    Client {
+        status: StatusCode,
+        body: Option<ApiError>,
+    },
+    /// Discord answered with a 5xx status.
+    Server {
+        status: StatusCode,
+        body: Option<ApiError>,
+    },
+    /// Still rate limited after exhausting every retry.
+    RateLimited {
+        retry_after: Duration,
+        global: bool,
+    },
+    /// Discord answered with a status we don't know how to handle.
+    UnexpectedStatus(StatusCode),
+    /// Refused to leave a guild the user owns, since Discord won't allow it.
+    OwnedGuild {
+        id: Snowflake,
+        name: String,
+    },
+    /// Refused to leave a guild on the protected-guild allowlist.
+    ProtectedGuild {
+        id: Snowflake,
+        name: String,
+        reason: String,
+    },
+    /// The token vault couldn't be unlocked, read or written.
+    Vault(String),
+    /// The journal holds something it can't make sense of.
+    Journal(String),
+    /// The config file isn't valid.
+    Config(String),
+    Io(std::io::Error),
+}
+
+impl Error {
+    /// Turns a non-successful response into the matching error variant,
+    /// parsing Discord's JSON error body when there is one.
+    pub async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let body = match response.bytes().await {
+            Ok(bytes) => serde_json::from_slice::<ApiError>(&bytes).ok(),
+            Err(e) => return Error::Transport(e),
+        };
+
+        if status.is_client_error() {
+            Error::Client { status, body }
+        } else if status.is_server_error() {
+            Error::Server { status, body }
+        } else {
+            Error::UnexpectedStatus(status)
+        }
+    }
+
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Client { status, .. } | Error::Server { status, .. } => Some(*status),
+            Error::UnexpectedStatus(status) => Some(*status),
+            Error::RateLimited { .. } => Some(StatusCode::TOO_MANY_REQUESTS),
+            Error::Transport(e) => e.status(),
+            _ => None,
+        }
+    }
+
+    /// Discord's JSON error code, if the response carried one.
+    pub fn code(&self) -> Option<u64> {
+        match self {
+            Error::Client { body, .. } | Error::Server { body, .. } => {
+                body.as_ref().map(|body| body.code)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Transport(e) => write!(f, "request failed: {}", e),
+            Error::Decode(e) => write!(f, "failed to decode response: {}", e),
+            Error::Client { status, body } | Error::Server { status, body } => {
+                write!(f, "Discord returned {}", status)?;
+                if let Some(body) = body {
+                    write!(f, ": {}", body)?;
+                }
+                Ok(())
+            }
+            Error::RateLimited {
+                retry_after,
+                global,
+            } => write!(
+                f,
+                "still rate limited{} after retrying, try again in {:?}",
+                if *global { " globally" } else { "" },
+                retry_after
+            ),
+            Error::UnexpectedStatus(status) => write!(f, "unexpected response status {}", status),
+            Error::OwnedGuild { id, name } => write!(
+                f,
+                "you own guild {} ({}), so it can't be left; transfer ownership or delete it instead",
+                name, id
+            ),
+            Error::ProtectedGuild { id, name, reason } => write!(
+                f,
+                "guild {} ({}) is protected: {}; use --force to leave it anyway",
+                name, id, reason
+            ),
+            Error::Vault(message) => write!(f, "Vault error: {}", message),
+            Error::Journal(message) => write!(f, "Journal error: {}", message),
+            Error::Config(message) => write!(f, "Config error: {}", message),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Transport(e) => Some(e),
+            Error::Decode(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Transport(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Decode(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
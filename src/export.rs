@@ -0,0 +1,218 @@
+//! Writing guild lists out as JSON, NDJSON, CSV, Markdown or an aligned table.
+
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::cli::OutputFormat;
+use crate::error::Result;
+use crate::guild::Guild;
+use crate::snowflake::Snowflake;
+
+/// A `Guild` field that can be exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Column {
+    Id,
+    Name,
+    Icon,
+    Owner,
+    Permissions,
+    Features,
+    Members,
+    Online,
+    /// When the current user joined. Not included by default, since it
+    /// takes a request per guild.
+    Joined,
+    /// When the guild was created, decoded from its ID.
+    Created,
+    /// When the guild's newest message was posted. Not included by
+    /// default, since it takes a request per guild.
+    LastMessage,
+}
+
+impl Column {
+    pub const ALL: &'static [Column] = &[
+        Column::Id,
+        Column::Name,
+        Column::Icon,
+        Column::Owner,
+        Column::Permissions,
+        Column::Features,
+        Column::Members,
+        Column::Online,
+    ];
+
+    /// The field's name in exported objects, which for the columns that
+    /// aren't derived is its name in the serialized `Guild`.
+    fn key(self) -> &'static str {
+        match self {
+            Column::Id => "id",
+            Column::Name => "name",
+            Column::Icon => "icon",
+            Column::Owner => "owner",
+            Column::Permissions => "permissions",
+            Column::Features => "features",
+            Column::Members => "approximate_member_count",
+            Column::Online => "approximate_presence_count",
+            Column::Joined => "joined_at",
+            Column::Created => "created_at",
+            Column::LastMessage => "last_message_at",
+        }
+    }
+
+    /// The field's value in a serialized `Guild`.
+    fn value(self, guild: &Value) -> Value {
+        match self {
+            Column::Joined => guild["member"]["joined_at"].clone(),
+            Column::LastMessage => guild["activity"]["last_message_at"].clone(),
+            // Serialized like the other timestamps, so every column agrees.
+            Column::Created => Snowflake::deserialize(&guild["id"])
+                .ok()
+                .and_then(|id| serde_json::to_value(id.created_at()).ok())
+                .unwrap_or(Value::Null),
+            column => guild[column.key()].clone(),
+        }
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            Column::Members => "members",
+            Column::Online => "online",
+            Column::Joined => "joined",
+            Column::Created => "created",
+            Column::LastMessage => "last_message",
+            column => column.key(),
+        }
+    }
+}
+
+/// Renders a JSON value as a single table or CSV cell.
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(cell).collect::<Vec<_>>().join(","),
+        value => value.to_string(),
+    }
+}
+
+/// Prints serializable records as pretty JSON, or one compact object per
+/// line for NDJSON.
+pub fn print_json<T: Serialize>(records: &[T], format: OutputFormat) -> Result<()> {
+    if format == OutputFormat::Ndjson {
+        for record in records {
+            println!("{}", serde_json::to_string(record)?);
+        }
+    } else {
+        println!("{}", serde_json::to_string_pretty(records)?);
+    }
+    Ok(())
+}
+
+/// Writes guilds in any format except `Text`, limited to `columns`.
+pub fn write_guilds(
+    out: &mut impl Write,
+    guilds: &[Guild],
+    format: OutputFormat,
+    columns: &[Column],
+) -> Result<()> {
+    let rows = guilds
+        .iter()
+        .map(|guild| {
+            let value = serde_json::to_value(guild)?;
+            Ok(columns
+                .iter()
+                .map(|column| column.value(&value))
+                .collect::<Vec<_>>())
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let objects = || {
+        rows.iter().map(|row| {
+            columns
+                .iter()
+                .map(|column| column.key().to_string())
+                .zip(row.iter().cloned())
+                .collect::<serde_json::Map<_, _>>()
+        })
+    };
+
+    match format {
+        OutputFormat::Json => {
+            let objects: Vec<_> = objects().collect();
+            writeln!(out, "{}", serde_json::to_string_pretty(&objects)?)?;
+        }
+        OutputFormat::Ndjson => {
+            for object in objects() {
+                writeln!(out, "{}", serde_json::to_string(&object)?)?;
+            }
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            writer
+                .write_record(columns.iter().map(|column| column.header()))
+                .map_err(io::Error::from)?;
+            for row in &rows {
+                writer
+                    .write_record(row.iter().map(cell))
+                    .map_err(io::Error::from)?;
+            }
+            writer.flush()?;
+        }
+        OutputFormat::Table | OutputFormat::Markdown | OutputFormat::Text => {
+            let markdown = format == OutputFormat::Markdown;
+            let escape = |s: String| if markdown { s.replace('|', "\\|") } else { s };
+
+            let headers: Vec<String> = columns.iter().map(|c| c.header().to_string()).collect();
+            let cells: Vec<Vec<String>> = rows
+                .iter()
+                .map(|row| row.iter().map(|v| escape(cell(v))).collect())
+                .collect();
+
+            let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+            for row in &cells {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.chars().count());
+                }
+            }
+
+            let line = |row: &[String]| {
+                let padded: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                    .collect();
+                if markdown {
+                    format!("| {} |", padded.join(" | "))
+                } else {
+                    padded.join("  ").trim_end().to_string()
+                }
+            };
+
+            writeln!(out, "{}", line(&headers))?;
+            let rules: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+            writeln!(out, "{}", line(&rules))?;
+            for row in &cells {
+                writeln!(out, "{}", line(row))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn created_is_formatted_like_the_other_timestamps() {
+        let guild = serde_json::json!({
+            "id": "175928847299117063",
+            "member": { "joined_at": "2016-04-30T11:18:25.796Z" },
+        });
+        assert_eq!(Column::Created.value(&guild), Column::Joined.value(&guild));
+        assert_eq!(Column::Created.value(&serde_json::json!({})), Value::Null);
+    }
+}
@@ -0,0 +1,784 @@
+//! A small expression language for picking guilds, e.g.
+//! `name ~ /crypto/i and not owner and members > 10000 and not feature:VERIFIED`.
+//!
+//! ```text
+//! expr       := and ("or" and)*
+//! and        := unary ("and" unary)*
+//! unary      := "not" unary | "(" expr ")" | comparison | flag
+//! comparison := field op value
+//! flag       := "owner" | "admin" | "feature:" NAME
+//! field      := "name" | "id" | "members" | "online" | "joined" | "created" | "inactive"
+//! op         := "~" | "!~" | "=" | "!=" | ">" | ">=" | "<" | "<="
+//! value      := NUMBER | "quoted string" | /regex/flags | bareword
+//! ```
+//!
+//! `joined` is how many days ago the current user joined the guild, so
+//! `joined > 365` picks guilds joined more than a year ago. `created` is how
+//! many days ago the guild itself was created, going by its ID, and
+//! `inactive` how many days ago anyone last posted in it.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use regex::{Regex, RegexBuilder};
+
+use crate::guild::Guild;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Character offset into the expression where things went wrong.
+    pub position: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at column {}: {}", self.position + 1, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Number(u64),
+    Text(String),
+    Regex(String, String),
+    Op(Op),
+    LParen,
+    RParen,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "`{}`", word),
+            Token::Number(n) => write!(f, "`{}`", n),
+            Token::Text(text) => write!(f, "\"{}\"", text),
+            Token::Regex(pattern, flags) => write!(f, "/{}/{}", pattern, flags),
+            Token::Op(op) => write!(f, "`{}`", op),
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Matches,
+    NotMatches,
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Op::Matches => "~",
+            Op::NotMatches => "!~",
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+        })
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+
+        let token = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                i += 1;
+                Token::LParen
+            }
+            ')' => {
+                i += 1;
+                Token::RParen
+            }
+            '~' | '=' | '!' | '<' | '>' => {
+                let next = chars.get(i + 1).copied();
+                let (op, len) = match (c, next) {
+                    ('~', _) => (Op::Matches, 1),
+                    ('=', Some('=')) => (Op::Eq, 2),
+                    ('=', _) => (Op::Eq, 1),
+                    ('!', Some('=')) => (Op::Ne, 2),
+                    ('!', Some('~')) => (Op::NotMatches, 2),
+                    ('<', Some('=')) => (Op::Le, 2),
+                    ('<', _) => (Op::Lt, 1),
+                    ('>', Some('=')) => (Op::Ge, 2),
+                    ('>', _) => (Op::Gt, 1),
+                    _ => return Err(ParseError::new(start, "expected `!=` or `!~`")),
+                };
+                i += len;
+                Token::Op(op)
+            }
+            '"' | '\'' => {
+                let quote = c;
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(ParseError::new(start, "unterminated string")),
+                        Some('\\') if chars.get(i + 1).is_some() => {
+                            text.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some(&ch) if ch == quote => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&ch) => {
+                            text.push(ch);
+                            i += 1;
+                        }
+                    }
+                }
+                Token::Text(text)
+            }
+            '/' => {
+                let mut pattern = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(ParseError::new(start, "unterminated regex")),
+                        Some('\\') if chars.get(i + 1) == Some(&'/') => {
+                            pattern.push('/');
+                            i += 2;
+                        }
+                        Some('/') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&ch) => {
+                            pattern.push(ch);
+                            i += 1;
+                        }
+                    }
+                }
+                let mut flags = String::new();
+                while let Some(&ch) = chars.get(i).filter(|ch| ch.is_ascii_alphabetic()) {
+                    flags.push(ch);
+                    i += 1;
+                }
+                Token::Regex(pattern, flags)
+            }
+            c if c.is_ascii_digit() => {
+                let mut digits = String::new();
+                while let Some(&ch) = chars.get(i).filter(|ch| ch.is_ascii_digit() || **ch == '_') {
+                    if ch != '_' {
+                        digits.push(ch);
+                    }
+                    i += 1;
+                }
+                let n = digits
+                    .parse()
+                    .map_err(|_| ParseError::new(start, "number is too large"))?;
+                Token::Number(n)
+            }
+            c if is_word_char(c) => {
+                let mut word = String::new();
+                while let Some(&ch) = chars.get(i).filter(|ch| is_word_char(**ch)) {
+                    word.push(ch);
+                    i += 1;
+                }
+                Token::Word(word)
+            }
+            c => {
+                return Err(ParseError::new(
+                    start,
+                    format!("unexpected character `{}`", c),
+                ))
+            }
+        };
+
+        tokens.push((start, token));
+    }
+
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | ':' | '.')
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Name,
+    Id,
+    Members,
+    Online,
+    Joined,
+    Created,
+    Inactive,
+}
+
+impl Field {
+    fn parse(word: &str) -> Option<Self> {
+        match word.to_ascii_lowercase().as_str() {
+            "name" => Some(Field::Name),
+            "id" => Some(Field::Id),
+            "members" => Some(Field::Members),
+            "online" | "presence" => Some(Field::Online),
+            "joined" => Some(Field::Joined),
+            "created" => Some(Field::Created),
+            "inactive" => Some(Field::Inactive),
+            _ => None,
+        }
+    }
+
+    fn is_numeric(self) -> bool {
+        !matches!(self, Field::Name)
+    }
+
+    fn number(self, guild: &Guild) -> Option<u64> {
+        match self {
+            Field::Name => None,
+            Field::Id => Some(guild.id.0),
+            Field::Members => guild.approximate_member_count,
+            Field::Online => guild.approximate_presence_count,
+            Field::Joined => guild.joined_at().map(days_since),
+            Field::Created => Some(days_since(guild.id.created_at())),
+            Field::Inactive => guild.last_active_at().map(days_since),
+        }
+    }
+
+    fn text(self, guild: &Guild) -> String {
+        match self {
+            Field::Name => guild.name.clone(),
+            Field::Id => guild.id.to_string(),
+            _ => self
+                .number(guild)
+                .map(|n| n.to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn days_since(time: DateTime<Utc>) -> u64 {
+    (Utc::now() - time).num_days().max(0) as u64
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Number(u64),
+    Text(String),
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Owner,
+    Admin,
+    Feature(String),
+    Compare(Field, Op, Value),
+}
+
+impl Expr {
+    fn uses(&self, field: Field) -> bool {
+        match self {
+            Expr::And(a, b) | Expr::Or(a, b) => a.uses(field) || b.uses(field),
+            Expr::Not(e) => e.uses(field),
+            Expr::Compare(f, _, _) => *f == field,
+            Expr::Owner | Expr::Admin | Expr::Feature(_) => false,
+        }
+    }
+
+    fn matches(&self, guild: &Guild) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches(guild) && b.matches(guild),
+            Expr::Or(a, b) => a.matches(guild) || b.matches(guild),
+            Expr::Not(e) => !e.matches(guild),
+            Expr::Owner => guild.owner,
+            Expr::Admin => guild.owner || guild.permissions.is_admin(),
+            Expr::Feature(feature) => guild
+                .features
+                .iter()
+                .any(|f| f.eq_ignore_ascii_case(feature)),
+            Expr::Compare(field, op, Value::Number(n)) => match field.number(guild) {
+                Some(actual) => match op {
+                    Op::Eq => actual == *n,
+                    Op::Ne => actual != *n,
+                    Op::Gt => actual > *n,
+                    Op::Ge => actual >= *n,
+                    Op::Lt => actual < *n,
+                    Op::Le => actual <= *n,
+                    Op::Matches | Op::NotMatches => false,
+                },
+                None => false,
+            },
+            Expr::Compare(field, op, Value::Text(text)) => {
+                let actual = field.text(guild);
+                match op {
+                    Op::Eq => actual.eq_ignore_ascii_case(text),
+                    Op::Ne => !actual.eq_ignore_ascii_case(text),
+                    _ => false,
+                }
+            }
+            Expr::Compare(field, op, Value::Regex(regex)) => {
+                let found = regex.is_match(&field.text(guild));
+                match op {
+                    Op::NotMatches => !found,
+                    _ => found,
+                }
+            }
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map(|(position, _)| *position)
+            .unwrap_or(self.end)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(_, token)| token.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.unary()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+
+        let position = self.position();
+        match self.next() {
+            Some(Token::LParen) => {
+                let expr = self.expr()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err(ParseError::new(position, "unclosed `(`")),
+                }
+            }
+            Some(Token::Word(word)) => self.term(position, &word),
+            Some(token) => Err(ParseError::new(
+                position,
+                format!("expected a field or flag, found {}", token),
+            )),
+            None => Err(ParseError::new(position, "expected a field or flag")),
+        }
+    }
+
+    fn term(&mut self, position: usize, word: &str) -> Result<Expr, ParseError> {
+        let lower = word.to_ascii_lowercase();
+        if let Some(feature) = lower.strip_prefix("feature:") {
+            if feature.is_empty() {
+                return Err(ParseError::new(
+                    position,
+                    "expected a feature name after `feature:`",
+                ));
+            }
+            return Ok(Expr::Feature(feature.to_string()));
+        }
+        match lower.as_str() {
+            "owner" => return Ok(Expr::Owner),
+            "admin" => return Ok(Expr::Admin),
+            _ => {}
+        }
+
+        let Some(field) = Field::parse(word) else {
+            return Err(ParseError::new(
+                position,
+                format!(
+                    "unknown field `{}`, expected one of name, id, members, online, joined, created, inactive, owner, admin or feature:NAME",
+                    word
+                ),
+            ));
+        };
+
+        let op_position = self.position();
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            _ => {
+                return Err(ParseError::new(
+                    op_position,
+                    format!("expected an operator after `{}`", word),
+                ))
+            }
+        };
+
+        let value_position = self.position();
+        let value = match (self.next(), op) {
+            (Some(Token::Regex(pattern, flags)), Op::Matches | Op::NotMatches) => {
+                Value::Regex(regex(value_position, &pattern, &flags)?)
+            }
+            (Some(Token::Text(text) | Token::Word(text)), Op::Matches | Op::NotMatches) => {
+                Value::Regex(regex(value_position, &regex::escape(&text), "i")?)
+            }
+            (Some(Token::Number(n)), Op::Matches | Op::NotMatches) => {
+                Value::Regex(regex(value_position, &n.to_string(), "")?)
+            }
+            (Some(Token::Number(n)), _) if field.is_numeric() => Value::Number(n),
+            (Some(Token::Text(text) | Token::Word(text)), Op::Eq | Op::Ne)
+                if !field.is_numeric() =>
+            {
+                Value::Text(text)
+            }
+            (Some(Token::Number(n)), Op::Eq | Op::Ne) => Value::Text(n.to_string()),
+            (Some(token), _) => {
+                return Err(ParseError::new(
+                    value_position,
+                    format!("can't compare `{}` {} {}", word, op, token),
+                ))
+            }
+            (None, _) => {
+                return Err(ParseError::new(
+                    value_position,
+                    format!("expected a value after `{} {}`", word, op),
+                ))
+            }
+        };
+
+        Ok(Expr::Compare(field, op, value))
+    }
+}
+
+fn regex(position: usize, pattern: &str, flags: &str) -> Result<Regex, ParseError> {
+    let mut builder = RegexBuilder::new(pattern);
+    for flag in flags.chars() {
+        match flag {
+            'i' => builder.case_insensitive(true),
+            'x' => builder.ignore_whitespace(true),
+            'm' => builder.multi_line(true),
+            's' => builder.dot_matches_new_line(true),
+            _ => {
+                return Err(ParseError::new(
+                    position,
+                    format!("unknown regex flag `{}`", flag),
+                ))
+            }
+        };
+    }
+    builder
+        .build()
+        .map_err(|e| ParseError::new(position, format!("invalid regex: {}", e)))
+}
+
+/// A parsed guild filter expression.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    source: String,
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn matches(&self, guild: &Guild) -> bool {
+        self.expr.matches(guild)
+    }
+
+    /// Whether the filter looks at [`Guild::member`], which has to be
+    /// fetched separately.
+    pub fn needs_members(&self) -> bool {
+        self.expr.uses(Field::Joined)
+    }
+
+    /// Whether the filter looks at [`Guild::activity`], which has to be
+    /// fetched separately.
+    pub fn needs_activity(&self) -> bool {
+        self.expr.uses(Field::Inactive)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = ParseError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: source.chars().count(),
+        };
+
+        let expr = parser.expr()?;
+        if let Some(token) = parser.peek() {
+            return Err(ParseError::new(
+                parser.position(),
+                format!(
+                    "expected `and`, `or` or the end of the filter, found {}",
+                    token
+                ),
+            ));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guild::{Member, Permissions};
+    use crate::snowflake::Snowflake;
+
+    fn guild(name: &str, members: u64) -> Guild {
+        Guild {
+            id: Snowflake(175928847299117063),
+            name: name.to_string(),
+            icon: None,
+            owner: false,
+            permissions: Permissions(0),
+            features: Vec::new(),
+            approximate_member_count: Some(members),
+            approximate_presence_count: None,
+            member: None,
+            activity: None,
+        }
+    }
+
+    fn matches(filter: &str, guild: &Guild) -> bool {
+        filter.parse::<Filter>().unwrap().matches(guild)
+    }
+
+    fn error(filter: &str) -> String {
+        filter.parse::<Filter>().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let owned = Guild {
+            owner: true,
+            ..guild("Mine", 5)
+        };
+        // owner or (members > 100 and admin)
+        assert!(matches("owner or members > 100 and admin", &owned));
+        assert!(!matches(
+            "(owner or members > 100) and admin",
+            &guild("Big", 500)
+        ));
+        assert!(matches(
+            "(owner or members > 100) and not admin",
+            &guild("Big", 500)
+        ));
+    }
+
+    #[test]
+    fn not_applies_to_the_nearest_term() {
+        let small = guild("Small", 5);
+        assert!(matches("not owner and members < 10", &small));
+        assert!(!matches("not (owner or members < 10)", &small));
+        assert!(matches("not not members < 10", &small));
+    }
+
+    #[test]
+    fn keywords_are_case_insensitive() {
+        assert!(matches("NOT owner AND Members >= 5", &guild("Small", 5)));
+    }
+
+    #[test]
+    fn regex_flags() {
+        let rust = guild("Rust Programming", 10);
+        assert!(matches("name ~ /rust/i", &rust));
+        assert!(!matches("name ~ /rust/", &rust));
+        // `x` ignores whitespace in the pattern unless it is escaped.
+        assert!(matches(
+            "name ~ /^rust programming$/x",
+            &guild("rustprogramming", 1)
+        ));
+        assert!(matches("name ~ /^rust\\ programming$/ix", &rust));
+        assert_eq!(
+            error("name ~ /rust/q"),
+            "at column 8: unknown regex flag `q`"
+        );
+    }
+
+    #[test]
+    fn negated_regex() {
+        let crypto = guild("Crypto Moon", 10);
+        assert!(!matches("name !~ /crypto/i", &crypto));
+        assert!(matches("name !~ /rust/i", &crypto));
+        // Bare words and strings match case-insensitively as literals.
+        assert!(!matches("name !~ moon", &crypto));
+        assert!(matches("name ~ \"o m\"", &crypto));
+    }
+
+    #[test]
+    fn regex_with_escaped_slash() {
+        assert!(matches("name ~ /a\\/b/", &guild("a/b", 1)));
+    }
+
+    #[test]
+    fn quoted_strings_with_escapes() {
+        assert!(matches(r#"name = "Say \"hi\"""#, &guild("Say \"hi\"", 1)));
+        assert!(matches(r"name = 'it\'s'", &guild("it's", 1)));
+        assert!(matches(r#"name = "back\\slash""#, &guild("back\\slash", 1)));
+        assert!(matches("name = \"rust lang\"", &guild("Rust Lang", 1)));
+        assert_eq!(error("name = \"open"), "at column 8: unterminated string");
+    }
+
+    #[test]
+    fn features() {
+        let verified = Guild {
+            features: vec!["VERIFIED".to_string()],
+            ..guild("Official", 1)
+        };
+        assert!(matches("feature:verified", &verified));
+        assert!(!matches("feature:PARTNERED", &verified));
+        assert_eq!(
+            error("feature:"),
+            "at column 1: expected a feature name after `feature:`"
+        );
+    }
+
+    #[test]
+    fn unclosed_paren_points_at_the_paren() {
+        assert_eq!(
+            error("owner and (members > 1 or admin"),
+            "at column 11: unclosed `(`"
+        );
+    }
+
+    #[test]
+    fn unknown_field_points_at_the_field() {
+        let message = error("members > 1 and nope = 2");
+        assert!(
+            message.starts_with("at column 17: unknown field `nope`"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn other_errors_report_their_column() {
+        assert_eq!(
+            error("members >"),
+            "at column 10: expected a value after `members >`"
+        );
+        assert_eq!(
+            error("members 5"),
+            "at column 9: expected an operator after `members`"
+        );
+        assert_eq!(
+            error("owner admin"),
+            "at column 7: expected `and`, `or` or the end of the filter, found `admin`"
+        );
+        assert_eq!(error("members ! 5"), "at column 9: expected `!=` or `!~`");
+        assert_eq!(error(""), "at column 1: expected a field or flag");
+    }
+
+    #[test]
+    fn numbers_allow_underscores() {
+        assert!(matches("members > 1_000", &guild("Big", 1_001)));
+    }
+
+    #[test]
+    fn missing_counts_never_match() {
+        let unknown = Guild {
+            approximate_member_count: None,
+            ..guild("Unknown", 0)
+        };
+        assert!(!matches("members < 10", &unknown));
+        assert!(!matches("members >= 10", &unknown));
+    }
+
+    #[test]
+    fn needs_members_and_activity() {
+        let filter: Filter = "name ~ /x/ or (not joined > 30)".parse().unwrap();
+        assert!(filter.needs_members());
+        assert!(!filter.needs_activity());
+
+        let filter: Filter = "members > 5 and inactive > 90".parse().unwrap();
+        assert!(!filter.needs_members());
+        assert!(filter.needs_activity());
+
+        let filter: Filter = "owner or created > 365".parse().unwrap();
+        assert!(!filter.needs_members());
+        assert!(!filter.needs_activity());
+    }
+
+    #[test]
+    fn joined_uses_the_fetched_membership() {
+        let joined = Guild {
+            member: Some(Member {
+                user: None,
+                nick: None,
+                roles: Vec::new(),
+                joined_at: Some(Utc::now() - chrono::Duration::days(400)),
+                pending: false,
+            }),
+            ..guild("Old", 1)
+        };
+        assert!(matches("joined > 365", &joined));
+        // Without the membership there's nothing to compare against.
+        assert!(!matches("joined > 365", &guild("Old", 1)));
+    }
+}
@@ -0,0 +1,268 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::snowflake::Snowflake;
+use crate::user::User;
+
+/// A guild as returned by `/users/@me/guilds`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Guild {
+    pub id: Snowflake,
+    pub name: String,
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub owner: bool,
+    #[serde(default)]
+    pub permissions: Permissions,
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// Only present when the guilds were fetched with `with_counts=true`.
+    #[serde(default)]
+    pub approximate_member_count: Option<u64>,
+    #[serde(default)]
+    pub approximate_presence_count: Option<u64>,
+    /// The current user's membership, only filled in by
+    /// [`commands::fetch_members`](crate::commands::fetch_members).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub member: Option<Member>,
+    /// Only filled in by
+    /// [`commands::fetch_activity`](crate::commands::fetch_activity).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub activity: Option<Activity>,
+}
+
+impl Guild {
+    pub fn joined_at(&self) -> Option<DateTime<Utc>> {
+        self.member.as_ref()?.joined_at
+    }
+
+    /// When anything last happened in the guild: its last message, or its
+    /// creation if nobody has posted. `None` until its activity is fetched.
+    pub fn last_active_at(&self) -> Option<DateTime<Utc>> {
+        let activity = self.activity.as_ref()?;
+        Some(
+            activity
+                .last_message_at
+                .unwrap_or_else(|| self.id.created_at()),
+        )
+    }
+}
+
+/// How recently a guild's channels were posted in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Activity {
+    /// How many channels have ever had a message.
+    pub channels: usize,
+    pub last_message_at: Option<DateTime<Utc>>,
+}
+
+impl Activity {
+    pub fn from_channels(channels: &[Channel]) -> Self {
+        let last_messages: Vec<_> = channels
+            .iter()
+            .filter(|channel| channel.has_messages())
+            .filter_map(|channel| channel.last_message_id)
+            .collect();
+        Self {
+            channels: last_messages.len(),
+            last_message_at: last_messages.iter().max().map(|id| id.created_at()),
+        }
+    }
+}
+
+/// A channel as returned by `/guilds/{guild.id}/channels`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Channel {
+    pub id: Snowflake,
+    #[serde(rename = "type")]
+    pub kind: u8,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The newest message's ID, even if that message has since been
+    /// deleted. Missing for categories and channels never posted in.
+    #[serde(default)]
+    pub last_message_id: Option<Snowflake>,
+}
+
+impl Channel {
+    /// Whether messages can be posted in the channel: text, voice,
+    /// announcement, stage, forum and media channels.
+    pub fn has_messages(&self) -> bool {
+        matches!(self.kind, 0 | 2 | 5 | 13 | 15 | 16)
+    }
+}
+
+impl fmt::Display for Guild {
+    /// Formats the guild's name followed by whatever is worth knowing before
+    /// leaving it, e.g. `Rust (12345 members, admin)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut details = Vec::new();
+        if let Some(members) = self.approximate_member_count {
+            details.push(format!("{} members", members));
+        }
+        if let Some(joined_at) = self.joined_at() {
+            details.push(format!("joined {}", joined_at.format("%Y-%m-%d")));
+        }
+        if self.owner {
+            details.push("owner".to_string());
+        } else if self.permissions.is_admin() {
+            details.push("admin".to_string());
+        }
+
+        if details.is_empty() {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{} ({})", self.name, details.join(", "))
+        }
+    }
+}
+
+/// The parts of a full guild object, as returned by `/guilds/{guild.id}`,
+/// that the partial one from `/users/@me/guilds` lacks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuildDetails {
+    pub id: Snowflake,
+    pub name: String,
+    pub owner_id: Snowflake,
+}
+
+/// A guild member as returned by `/guilds/{guild.id}/members` or, for the
+/// current user, `/users/@me/guilds/{guild.id}/member`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Member {
+    #[serde(default)]
+    pub user: Option<User>,
+    #[serde(default)]
+    pub nick: Option<String>,
+    /// Role IDs, not including `@everyone`.
+    #[serde(default)]
+    pub roles: Vec<Snowflake>,
+    #[serde(default)]
+    pub joined_at: Option<DateTime<Utc>>,
+    /// Whether the member has yet to pass membership screening.
+    #[serde(default)]
+    pub pending: bool,
+}
+
+impl fmt::Display for Member {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.user, &self.nick) {
+            (Some(user), Some(nick)) => write!(f, "{} ({})", nick, user),
+            (Some(user), None) => write!(f, "{}", user),
+            (None, nick) => write!(f, "{}", nick.as_deref().unwrap_or("unknown member")),
+        }
+    }
+}
+
+/// The current user's permission bitfield in a guild, which Discord sends as
+/// a string since it no longer fits in a JSON-safe integer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Permissions(pub u64);
+
+impl Permissions {
+    pub const ADMINISTRATOR: u64 = 1 << 3;
+
+    /// The permissions worth asking for by name, in bit order.
+    const NAMES: &'static [(&'static str, u64)] = &[
+        ("create_instant_invite", 1 << 0),
+        ("kick_members", 1 << 1),
+        ("ban_members", 1 << 2),
+        ("administrator", 1 << 3),
+        ("manage_channels", 1 << 4),
+        ("manage_guild", 1 << 5),
+        ("add_reactions", 1 << 6),
+        ("view_audit_log", 1 << 7),
+        ("view_channel", 1 << 10),
+        ("send_messages", 1 << 11),
+        ("manage_messages", 1 << 13),
+        ("embed_links", 1 << 14),
+        ("attach_files", 1 << 15),
+        ("read_message_history", 1 << 16),
+        ("mention_everyone", 1 << 17),
+        ("use_external_emojis", 1 << 18),
+        ("connect", 1 << 20),
+        ("speak", 1 << 21),
+        ("change_nickname", 1 << 26),
+        ("manage_nicknames", 1 << 27),
+        ("manage_roles", 1 << 28),
+        ("manage_webhooks", 1 << 29),
+        ("use_application_commands", 1 << 31),
+        ("moderate_members", 1 << 40),
+    ];
+
+    pub fn is_admin(self) -> bool {
+        self.0 & Self::ADMINISTRATOR != 0
+    }
+
+    /// The permissions in `required` these lack. Administrators lack none.
+    pub fn missing(self, required: Permissions) -> Permissions {
+        if self.is_admin() {
+            return Permissions(0);
+        }
+        Permissions(required.0 & !self.0)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The names of the set bits, with unnamed ones as raw numbers.
+    pub fn names(self) -> Vec<String> {
+        (0..64)
+            .map(|bit| 1u64 << bit)
+            .filter(|flag| self.0 & flag != 0)
+            .map(|flag| match Self::NAMES.iter().find(|(_, f)| *f == flag) {
+                Some((name, _)) => name.to_string(),
+                None => flag.to_string(),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Permissions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Parses either the raw bitfield or permission names separated by commas,
+/// e.g. `send_messages,embed_links`.
+impl FromStr for Permissions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(bits) = s.trim().parse() {
+            return Ok(Permissions(bits));
+        }
+
+        s.split(',')
+            .map(|name| name.trim().to_ascii_lowercase())
+            .filter(|name| !name.is_empty())
+            .try_fold(0, |bits, name| {
+                match Self::NAMES.iter().find(|(known, _)| *known == name) {
+                    Some((_, flag)) => Ok(bits | flag),
+                    None => Err(format!("unknown permission `{}`", name)),
+                }
+            })
+            .map(Permissions)
+    }
+}
+
+impl Serialize for Permissions {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Permissions {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        raw.parse::<u64>()
+            .map(Permissions)
+            .map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,207 @@
+use tracing::error;
+
+use crate::cli::OutputFormat;
+use crate::client::DiscordClient;
+use crate::commands::{self, Action};
+use crate::error::Result;
+use crate::filter::Filter;
+use crate::journal::{Outcome, PlannedGuild, RunLog};
+use crate::mute::MuteDuration;
+use crate::owned;
+use crate::prompt::{self, read_line};
+use crate::user::CurrentUser;
+
+#[derive(Debug, Clone, Copy)]
+enum Choice {
+    MassLeave,
+    MassMute,
+    MassUnmute,
+    ManageOwned,
+    Exit,
+}
+
+impl Choice {
+    fn label(self) -> &'static str {
+        match self {
+            Choice::MassLeave => "Mass leave guilds",
+            Choice::MassMute => "Mass mute guilds",
+            Choice::MassUnmute => "Mass unmute guilds",
+            Choice::ManageOwned => "Manage owned guilds",
+            Choice::Exit => "Exit",
+        }
+    }
+}
+
+pub async fn run(client: &DiscordClient, user: &CurrentUser) -> Result<()> {
+    // Notification settings only exist for user accounts.
+    let choices: Vec<Choice> = if client.is_bot() {
+        vec![Choice::MassLeave, Choice::ManageOwned, Choice::Exit]
+    } else {
+        vec![
+            Choice::MassLeave,
+            Choice::MassMute,
+            Choice::MassUnmute,
+            Choice::ManageOwned,
+            Choice::Exit,
+        ]
+    };
+
+    loop {
+        println!("What would you like to do?");
+        for (number, choice) in choices.iter().enumerate() {
+            println!("{}. {}", number + 1, choice.label());
+        }
+
+        let input = read_line()?;
+        let choice = input
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| choices.get(number.checked_sub(1)?));
+
+        match choice {
+            Some(Choice::MassLeave) => mass_run(client, Action::Leave { force: false }).await?,
+            Some(Choice::MassMute) => {
+                let until = ask_mute_duration()?.map(MuteDuration::end_time);
+                mass_run(client, Action::Mute { until }).await?
+            }
+            Some(Choice::MassUnmute) => mass_run(client, Action::Unmute).await?,
+            Some(Choice::ManageOwned) => owned::manage(client, user).await?,
+            Some(Choice::Exit) => break,
+            None => println!("Invalid input! Please try again."),
+        }
+    }
+
+    Ok(())
+}
+
+/// Asks about each guild, optionally narrowed down by a filter, then does
+/// `action` to the chosen ones all at once.
+async fn mass_run(client: &DiscordClient, action: Action) -> Result<()> {
+    let mut guilds = match client.get_guilds().await {
+        Ok(guilds) => guilds,
+        Err(e) => {
+            error!("Failed to get guilds: {}", e);
+            return Ok(());
+        }
+    };
+    if guilds.is_empty() {
+        println!("No guilds found.");
+        return Ok(());
+    }
+    let guilds = match ask_filter()? {
+        Some(filter) => {
+            if filter.needs_members() {
+                commands::fetch_members(client, &mut guilds).await;
+            }
+            if filter.needs_activity() {
+                commands::fetch_activity(client, &mut guilds).await;
+            }
+            let matched: Vec<_> = guilds.into_iter().filter(|g| filter.matches(g)).collect();
+            println!("{} guild(s) matched the filter.", matched.len());
+            matched
+        }
+        None => guilds,
+    };
+
+    // Muting works anywhere, but owned and protected guilds can't be left.
+    let leaving = matches!(action, Action::Leave { .. });
+    let (owned, guilds): (Vec<_>, Vec<_>) =
+        guilds.into_iter().partition(|guild| leaving && guild.owner);
+    if !owned.is_empty() {
+        println!(
+            "Skipping {} guild(s) you own, since Discord doesn't let owners leave:",
+            owned.len()
+        );
+        for guild in &owned {
+            println!("  {}", guild.name);
+        }
+        println!("Use \"Manage owned guilds\" to transfer or delete them instead.");
+    }
+
+    let (protected, guilds): (Vec<_>, Vec<_>) = guilds
+        .into_iter()
+        .partition(|guild| leaving && client.allowlist().protects(guild).is_some());
+    if !protected.is_empty() {
+        println!("Skipping {} protected guild(s):", protected.len());
+        for guild in &protected {
+            let reason = client.allowlist().protects(guild).unwrap_or_default();
+            println!("  {} ({})", guild.name, reason);
+        }
+    }
+
+    let plan = action.plan(
+        client.is_dry_run(),
+        false,
+        guilds
+            .iter()
+            .map(|guild| PlannedGuild {
+                id: guild.id,
+                name: Some(guild.name.clone()),
+            })
+            .collect(),
+    );
+    let mut log = RunLog::start(client.journal(), &plan);
+    let mut chosen = Vec::new();
+
+    // Ask for each guild if they want to act on it, then act on them all at once
+    for (index, planned) in plan.guilds.iter().enumerate() {
+        let guild = &guilds[index];
+        let question = format!("Would you like to {} guild {}", action.verb(), guild);
+        if prompt::confirm(&question)? {
+            chosen.push((index, planned));
+        } else {
+            log.record(index, guild.id, Outcome::Skipped);
+            println!(
+                "Skipped {} guild {}.",
+                action.progressive().to_lowercase(),
+                guild.name
+            );
+        }
+    }
+
+    commands::run_planned(
+        client,
+        action,
+        &guilds,
+        chosen,
+        &mut log,
+        None,
+        OutputFormat::Text,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Asks how long to mute for, re-prompting until it parses. `None` mutes
+/// until the guilds are unmuted.
+fn ask_mute_duration() -> Result<Option<MuteDuration>> {
+    loop {
+        println!("Mute for how long (e.g. `8h` or `7d`), or press enter until you unmute them:");
+
+        let input = read_line()?;
+        if input.trim().is_empty() {
+            return Ok(None);
+        }
+        match input.trim().parse() {
+            Ok(duration) => return Ok(Some(duration)),
+            Err(e) => println!("Invalid duration: {}. Please try again.", e),
+        }
+    }
+}
+
+/// Asks for an optional filter expression, re-prompting until it parses.
+fn ask_filter() -> Result<Option<Filter>> {
+    loop {
+        println!("Filter guilds (e.g. `members > 1000 and not owner`), or press enter for all:");
+
+        let input = read_line()?;
+        if input.trim().is_empty() {
+            return Ok(None);
+        }
+        match input.trim().parse() {
+            Ok(filter) => return Ok(Some(filter)),
+            Err(e) => println!("Invalid filter {}. Please try again.", e),
+        }
+    }
+}
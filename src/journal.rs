@@ -0,0 +1,464 @@
+//! An append-only JSONL record of every mutating request the tool sends.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use reqwest::Response;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::error::{Error, Result};
+use crate::snowflake::Snowflake;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    LeaveGuild,
+    TransferOwnership,
+    DeleteGuild,
+    MuteGuild,
+    UnmuteGuild,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub timestamp: DateTime<Utc>,
+    pub operation: Operation,
+    pub guild_id: Snowflake,
+    pub guild_name: Option<String>,
+    /// The HTTP status Discord answered with, if a response came back at all.
+    pub status: Option<u16>,
+    /// Discord's JSON error code, for failed requests that carried one.
+    pub error_code: Option<u64>,
+    pub error: Option<String>,
+    pub dry_run: bool,
+}
+
+impl Entry {
+    pub fn new(
+        operation: Operation,
+        guild_id: Snowflake,
+        guild_name: Option<&str>,
+        result: &Result<Response>,
+        dry_run: bool,
+    ) -> Self {
+        let (status, error_code, error) = match result {
+            Ok(response) => (Some(response.status().as_u16()), None, None),
+            Err(e) => (
+                e.status().map(|status| status.as_u16()),
+                e.code(),
+                Some(e.to_string()),
+            ),
+        };
+
+        Self {
+            timestamp: Utc::now(),
+            operation,
+            guild_id,
+            guild_name: guild_name.map(str::to_string),
+            status,
+            error_code,
+            error,
+            dry_run,
+        }
+    }
+}
+
+/// One guild in a run's plan.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedGuild {
+    pub id: Snowflake,
+    pub name: Option<String>,
+}
+
+/// What a bulk run set out to do, written before its first request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    pub run_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub operation: Operation,
+    pub dry_run: bool,
+    /// Whether the user agreed to the whole plan up front, rather than
+    /// being asked about each guild as the run reached it.
+    pub confirmed: bool,
+    /// When a mute run's mutes end, so resuming it doesn't extend them.
+    /// `None` for mutes that last until they are undone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mute_until: Option<DateTime<Utc>>,
+    pub guilds: Vec<PlannedGuild>,
+}
+
+impl Plan {
+    pub fn new(
+        operation: Operation,
+        dry_run: bool,
+        confirmed: bool,
+        guilds: Vec<PlannedGuild>,
+    ) -> Self {
+        let timestamp = Utc::now();
+        Self {
+            // The process ID tells apart runs started in the same
+            // millisecond, e.g. a dry run and the real run right after it.
+            run_id: format!(
+                "{}-{}",
+                timestamp.format("%Y%m%d-%H%M%S%3f"),
+                std::process::id()
+            ),
+            timestamp,
+            operation,
+            dry_run,
+            confirmed,
+            mute_until: None,
+            guilds,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Done,
+    Failed,
+    /// The user chose not to act on the guild.
+    Skipped,
+}
+
+/// Moves a run's cursor past a guild once it has been dealt with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Progress {
+    pub run_id: String,
+    pub timestamp: DateTime<Utc>,
+    /// How many guilds at the start of the plan have all been dealt with.
+    pub cursor: usize,
+    pub guild_id: Snowflake,
+    pub outcome: Outcome,
+}
+
+/// Any line of the journal. Untagged so that plain action entries stay as
+/// they always were.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Record {
+    Plan(Plan),
+    Progress(Progress),
+    /// Action entries, which resuming doesn't need.
+    Other(IgnoredAny),
+}
+
+/// A run's plan together with everything the journal says happened since.
+pub struct Run {
+    pub plan: Plan,
+    cursor: usize,
+    outcomes: HashMap<Snowflake, Outcome>,
+}
+
+impl Run {
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// The planned guilds still to be dealt with, with their positions in
+    /// the plan: everything past the cursor, plus everything that failed.
+    /// Guilds only done in a dry run are still pending for a real one.
+    pub fn pending(&self, dry_run: bool) -> Vec<(usize, &PlannedGuild)> {
+        self.plan
+            .guilds
+            .iter()
+            .enumerate()
+            .filter(|(i, guild)| match self.outcomes.get(&guild.id) {
+                Some(Outcome::Failed) => true,
+                Some(Outcome::Done) => self.plan.dry_run && !dry_run,
+                Some(Outcome::Skipped) => false,
+                None => *i >= self.cursor,
+            })
+            .collect()
+    }
+}
+
+/// Journals a run's progress as it goes, so `resume` can pick it up later.
+pub struct RunLog<'a> {
+    journal: Option<&'a Journal>,
+    run_id: String,
+    cursor: usize,
+    /// Guilds past the cursor that are already dealt with, since guilds
+    /// don't necessarily finish in plan order.
+    finished: BTreeSet<usize>,
+}
+
+impl<'a> RunLog<'a> {
+    pub fn start(journal: Option<&'a Journal>, plan: &Plan) -> Self {
+        if let Some(journal) = journal {
+            journal.append(plan);
+            info!(
+                "Started run {}. If it gets interrupted, continue it with `resume {}`.",
+                plan.run_id, plan.run_id
+            );
+        }
+        Self {
+            journal,
+            run_id: plan.run_id.clone(),
+            cursor: 0,
+            finished: BTreeSet::new(),
+        }
+    }
+
+    /// Continues a run's log. A dry run over a real run's plan leaves no
+    /// progress behind, since it hasn't really done anything.
+    pub fn resume(journal: Option<&'a Journal>, run: &Run, dry_run: bool) -> Self {
+        let pending: BTreeSet<usize> = run.pending(dry_run).iter().map(|(i, _)| *i).collect();
+        Self {
+            journal: journal.filter(|_| run.plan.dry_run || !dry_run),
+            run_id: run.plan.run_id.clone(),
+            cursor: run.cursor,
+            finished: (run.cursor..run.plan.guilds.len())
+                .filter(|i| !pending.contains(i))
+                .collect(),
+        }
+    }
+
+    /// Records what happened to the guild at `index` in the plan. The
+    /// cursor only moves past guilds once everything before them is done.
+    pub fn record(&mut self, index: usize, guild_id: Snowflake, outcome: Outcome) {
+        self.finished.insert(index);
+        while self.finished.remove(&self.cursor) {
+            self.cursor += 1;
+        }
+        if let Some(journal) = self.journal {
+            journal.append(&Progress {
+                run_id: self.run_id.clone(),
+                timestamp: Utc::now(),
+                cursor: self.cursor,
+                guild_id,
+                outcome,
+            });
+        }
+    }
+}
+
+pub struct Journal {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl Journal {
+    /// `<data dir>/discord-manager-rust/journal.jsonl`, e.g.
+    /// `~/.local/share/discord-manager-rust/journal.jsonl` on Linux.
+    pub fn default_path() -> Result<PathBuf> {
+        dirs::data_dir()
+            .map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("journal.jsonl"))
+            .ok_or_else(|| io::Error::other("could not determine the data directory").into())
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends an entry.
+    pub fn record(&self, entry: &Entry) {
+        self.append(entry);
+    }
+
+    /// Reads back everything recorded about a run, or `None` if the journal
+    /// has no plan with that ID. Fails if more than one plan has it, since
+    /// their progress can't be told apart.
+    pub fn load_run(&self, run_id: &str) -> Result<Option<Run>> {
+        let contents = fs::read_to_string(&self.path)?;
+
+        let mut run: Option<Run> = None;
+        for (number, line) in contents.lines().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(Record::Plan(plan)) if plan.run_id == run_id => {
+                    if run.is_some() {
+                        return Err(Error::Journal(format!(
+                            "{} has more than one run with the ID {}",
+                            self.path.display(),
+                            run_id
+                        )));
+                    }
+                    run = Some(Run {
+                        plan,
+                        cursor: 0,
+                        outcomes: HashMap::new(),
+                    });
+                }
+                Ok(Record::Progress(progress)) if progress.run_id == run_id => {
+                    if let Some(run) = &mut run {
+                        run.cursor = run.cursor.max(progress.cursor);
+                        run.outcomes.insert(progress.guild_id, progress.outcome);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => warn!(
+                    "Ignoring unreadable line {} of the journal at {}: {}",
+                    number + 1,
+                    self.path.display(),
+                    e
+                ),
+            }
+        }
+        Ok(run)
+    }
+
+    /// Failing to write is logged rather than returned, so a full disk can't
+    /// leave an action half-reported to the caller.
+    fn append<T: Serialize>(&self, record: &T) {
+        let result = serde_json::to_string(record)
+            .map_err(io::Error::from)
+            .and_then(|line| {
+                let mut file = self.file.lock().unwrap();
+                writeln!(file, "{}", line)?;
+                file.flush()
+            });
+
+        if let Err(e) = result {
+            error!(
+                "Failed to write to the journal at {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh journal in the temp directory, named after the test using it.
+    fn journal(name: &str) -> Journal {
+        let path = std::env::temp_dir().join(format!(
+            "{}-{}-{}.jsonl",
+            env!("CARGO_PKG_NAME"),
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        Journal::open(&path).unwrap()
+    }
+
+    fn plan(dry_run: bool, guilds: u64) -> Plan {
+        Plan::new(
+            Operation::LeaveGuild,
+            dry_run,
+            true,
+            (1..=guilds)
+                .map(|id| PlannedGuild {
+                    id: Snowflake(id),
+                    name: Some(format!("Guild {}", id)),
+                })
+                .collect(),
+        )
+    }
+
+    fn pending_ids(run: &Run, dry_run: bool) -> Vec<u64> {
+        run.pending(dry_run)
+            .into_iter()
+            .map(|(_, guild)| guild.id.0)
+            .collect()
+    }
+
+    #[test]
+    fn cursor_waits_for_earlier_guilds() {
+        let plan = plan(false, 4);
+        let mut log = RunLog::start(None, &plan);
+
+        log.record(2, Snowflake(3), Outcome::Done);
+        assert_eq!(log.cursor, 0);
+        log.record(0, Snowflake(1), Outcome::Done);
+        assert_eq!(log.cursor, 1);
+        log.record(1, Snowflake(2), Outcome::Failed);
+        assert_eq!(log.cursor, 3);
+        log.record(3, Snowflake(4), Outcome::Skipped);
+        assert_eq!(log.cursor, 4);
+    }
+
+    #[test]
+    fn resumed_run_retries_failures_and_keeps_skips() {
+        let journal = journal("resume");
+        let plan = plan(false, 5);
+        let mut log = RunLog::start(Some(&journal), &plan);
+        log.record(1, Snowflake(2), Outcome::Done);
+        log.record(0, Snowflake(1), Outcome::Failed);
+        log.record(3, Snowflake(4), Outcome::Skipped);
+
+        let run = journal.load_run(&plan.run_id).unwrap().unwrap();
+        assert_eq!(run.cursor(), 2);
+        assert_eq!(pending_ids(&run, false), [1, 3, 5]);
+
+        // The failed guild is retried and done this time, and the skipped
+        // one doesn't hold the cursor back.
+        let mut log = RunLog::resume(Some(&journal), &run, false);
+        log.record(0, Snowflake(1), Outcome::Done);
+        log.record(2, Snowflake(3), Outcome::Done);
+        log.record(4, Snowflake(5), Outcome::Done);
+
+        let run = journal.load_run(&plan.run_id).unwrap().unwrap();
+        assert_eq!(run.cursor(), 5);
+        assert!(pending_ids(&run, false).is_empty());
+        let _ = fs::remove_file(journal.path());
+    }
+
+    #[test]
+    fn dry_run_plan_is_still_pending_for_real() {
+        let journal = journal("dry-run");
+        let plan = plan(true, 3);
+        let mut log = RunLog::start(Some(&journal), &plan);
+        for (index, guild) in plan.guilds.iter().enumerate() {
+            log.record(index, guild.id, Outcome::Done);
+        }
+
+        let run = journal.load_run(&plan.run_id).unwrap().unwrap();
+        assert!(pending_ids(&run, true).is_empty());
+        assert_eq!(pending_ids(&run, false), [1, 2, 3]);
+        let _ = fs::remove_file(journal.path());
+    }
+
+    #[test]
+    fn dry_run_over_a_real_plan_leaves_no_progress() {
+        let journal = journal("dry-resume");
+        let plan = plan(false, 2);
+        RunLog::start(Some(&journal), &plan);
+
+        let run = journal.load_run(&plan.run_id).unwrap().unwrap();
+        let mut log = RunLog::resume(Some(&journal), &run, true);
+        log.record(0, Snowflake(1), Outcome::Done);
+
+        let run = journal.load_run(&plan.run_id).unwrap().unwrap();
+        assert_eq!(run.cursor(), 0);
+        assert_eq!(pending_ids(&run, false), [1, 2]);
+        let _ = fs::remove_file(journal.path());
+    }
+
+    #[test]
+    fn load_run_rejects_duplicate_ids() {
+        let journal = journal("duplicate");
+        let plan = plan(false, 1);
+        journal.append(&plan);
+
+        assert!(journal.load_run("no-such-run").unwrap().is_none());
+        assert!(journal.load_run(&plan.run_id).unwrap().is_some());
+
+        journal.append(&plan);
+        let error = journal.load_run(&plan.run_id).err().unwrap();
+        assert!(matches!(error, Error::Journal(_)));
+        assert!(error.to_string().contains(&plan.run_id));
+        let _ = fs::remove_file(journal.path());
+    }
+}
@@ -1,197 +1,275 @@
+mod cli;
+mod client;
+mod commands;
+mod config;
+mod error;
+mod export;
+mod filter;
+mod guild;
+mod interactive;
+mod journal;
+mod mute;
+mod owned;
+mod progress;
+mod prompt;
+mod prune;
+mod ratelimit;
+mod snowflake;
+mod token;
+#[cfg(feature = "tui")]
+mod tui;
+mod user;
+mod vault;
+
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::process::ExitCode;
+
+use clap::Parser;
 use reqwest::StatusCode;
-use tracing::{error, info, info_span};
-
-async fn check_discord_token(token: &str) -> bool {
-    info!("Checking token...");
-
-    let client = reqwest::Client::new();
-    let response = client
-        .get("https://discord.com/api/v10/users/@me")
-        .header("Authorization", format!("{}", token))
-        .send()
-        .await;
-
-    match response {
-        Ok(response) => {
-            if response.status().is_success() {
-                let json: serde_json::Value = response.json().await.unwrap_or_default();
-                info!(
-                    "Token is valid! Welcome back {} ({})",
-                    json["username"], json["id"]
-                );
-                true
-            } else {
-                error!(
-                    "Token is invalid: {:?}",
-                    response.text().await.unwrap_or_default()
-                );
-                false
-            }
-        }
-        Err(e) => {
-            error!("Failed to check token: {:?}", e);
-            false
+use tracing::{error, info, info_span, warn};
+
+use crate::cli::{Cli, Command, Exit, GuildsCommand, VaultCommand};
+use crate::client::DiscordClient;
+use crate::commands::Action;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::journal::Journal;
+use crate::mute::MuteDuration;
+use crate::prune::Criteria;
+use crate::token::Token;
+use crate::vault::Vault;
+
+/// Finds the token in the first source that has one: the deprecated
+/// argument, stdin, the token file, the vault, `DISCORD_TOKEN`, the legacy
+/// `token.txt`, and finally a prompt. The vault is only read once the
+/// earlier sources come up empty, and only when `use_vault` is set.
+fn load_token(cli: &Cli, use_vault: bool) -> Result<Option<(Token, token::Source)>> {
+    if let Some(token) = &cli.token {
+        warn!(
+            "Passing the token as an argument is deprecated, since other users can see it in the process list. Use {}, --token-file or --token-stdin instead.",
+            token::ENV_VAR
+        );
+        return Ok(Some((token.clone(), token::Source::Argument)));
+    }
+    if cli.token_stdin {
+        return Ok(Some((token::from_stdin()?, token::Source::Stdin)));
+    }
+    if let Some(path) = &cli.token_file {
+        return Ok(Some((token::from_file(path)?, token::Source::File)));
+    }
+    if use_vault {
+        let mut vault = Vault::load(&Vault::default_path()?)?;
+        if let Some(token) = from_vault(&mut vault, cli.account.as_deref())? {
+            return Ok(Some((token, token::Source::Vault)));
         }
     }
+    if let Some(token) = token::from_env() {
+        return Ok(Some((token, token::Source::Env)));
+    }
+    let legacy = Path::new(token::LEGACY_FILE);
+    if legacy.exists() {
+        return Ok(Some((token::from_file(legacy)?, token::Source::File)));
+    }
+    Ok(token::from_prompt()?.map(|token| (token, token::Source::Prompt)))
 }
 
-#[derive(Debug, Clone)]
-struct Guild {
-    id: String,
-    name: String,
-}
+/// Picks the vault's token for `account`, or its only token. Without an
+/// account the vault is only unlocked when there's a terminal to ask for the
+/// passphrase on, so scripts fall through to the other sources.
+fn from_vault(vault: &mut Vault, account: Option<&str>) -> Result<Option<Token>> {
+    let names = vault.names();
+    let name = match (account, names.as_slice()) {
+        (Some(account), _) => account.to_string(),
+        (None, []) => return Ok(None),
+        (None, _) if !io::stdin().is_terminal() => return Ok(None),
+        (None, [name]) => name.to_string(),
+        (None, names) => {
+            return Err(Error::Vault(format!(
+                "it holds several tokens ({}); pick one with --account",
+                names.join(", ")
+            )))
+        }
+    };
 
-impl Guild {
-    fn new(id: String, name: String) -> Self {
-        Self { id, name }
+    match vault.get(&name)? {
+        Some(token) => Ok(Some(token)),
+        None => Err(Error::Vault(format!("there is no token named {}", name))),
     }
 }
 
-async fn get_guilds(token: &str) -> Vec<Guild> {
-    info!("Getting guilds...");
-
-    let client = reqwest::Client::new();
-    let response = client
-        .get("https://discord.com/api/v10/users/@me/guilds")
-        .header("Authorization", format!("{}", token))
-        .send()
-        .await;
-
-    match response {
-        Ok(response) => {
-            if response.status().is_success() {
-                let json: Vec<serde_json::Value> = response.json().await.unwrap_or_default();
-                let guilds = json
-                    .into_iter()
-                    .map(|guild| {
-                        Guild::new(
-                            guild["id"].as_str().unwrap_or_default().to_string(),
-                            guild["name"].as_str().unwrap_or_default().to_string(),
-                        )
-                    })
-                    .collect();
-
-                info!("Successfully got guilds!");
-                guilds
-            } else {
-                error!(
-                    "Failed to get guilds: {:?}",
-                    response.text().await.unwrap_or_default()
-                );
-                Vec::new()
-            }
-        }
-        Err(e) => {
-            error!("Failed to get guilds: {:?}", e);
-            Vec::new()
-        }
+async fn run(cli: Cli) -> Result<Exit> {
+    info!("Initializing...");
+
+    if let Some(Command::Decode { id }) = cli.command {
+        return commands::decode(id, cli.output);
     }
-}
 
-async fn leave_guild(token: &str, guild_id: &str) -> bool {
-    info!("Leaving guild {}...", guild_id);
-
-    let client = reqwest::Client::new();
-    let response = client
-        .delete(&format!(
-            "https://discord.com/api/v10/users/@me/guilds/{}",
-            guild_id
-        ))
-        .header("Authorization", format!("{}", token))
-        .send()
-        .await;
-
-    match response {
-        Ok(response) => {
-            if response.status() == StatusCode::NO_CONTENT {
-                info!("Successfully left guild {}!", guild_id);
-                true
-            } else {
-                error!(
-                    "Failed to leave guild {}: {:?}",
-                    guild_id,
-                    response.text().await.unwrap_or_default()
-                );
-                false
+    let config_path = match &cli.config {
+        Some(path) => path.clone(),
+        None => Config::default_path()?,
+    };
+    let mut config = Config::load(&config_path)?;
+
+    if let Some(Command::Allowlist(command)) = &cli.command {
+        return commands::allowlist(&mut config, &config_path, command, cli.output);
+    }
+
+    if let Some(Command::Vault(command)) = &cli.command {
+        let mut vault = Vault::load(&Vault::default_path()?)?;
+        let token = match command {
+            VaultCommand::Add { .. } => {
+                load_token(&cli, false)?
+                    .map(|(token, _)| if cli.bot { token.to_bot() } else { token })
             }
+            _ => None,
+        };
+        return commands::vault(&mut vault, command, token, cli.output);
+    }
+
+    let Some((token, source)) = load_token(&cli, true)? else {
+        error!(
+            "No token provided! Set {}, or pass --token-file or --token-stdin.",
+            token::ENV_VAR
+        );
+        return Ok(Exit::InvalidToken);
+    };
+    if token.is_empty() {
+        error!("The token from {} is empty.", source);
+        return Ok(Exit::InvalidToken);
+    }
+    info!("Using the token from {}.", source);
+    let token = if cli.bot { token.to_bot() } else { token };
+
+    let journal_path = match &cli.journal {
+        Some(path) => path.clone(),
+        None => Journal::default_path()?,
+    };
+    let journal = Journal::open(&journal_path)?;
+
+    let mut builder = DiscordClient::builder(token).env_overrides();
+    if let Some(concurrency) = cli.concurrency {
+        builder = builder.concurrency(concurrency.into());
+    }
+    let client = builder
+        .dry_run(cli.dry_run)
+        .allowlist(config.allowlist)
+        .journal(journal)
+        .build()?;
+    if client.is_dry_run() {
+        info!("Dry run: no guilds will actually be changed.");
+    }
+    let client = match client.detect_bot().await {
+        Ok(client) => client,
+        Err(e) if e.status() == Some(StatusCode::UNAUTHORIZED) => {
+            error!("Invalid token provided! Please provide a valid token.");
+            return Ok(Exit::InvalidToken);
         }
-        Err(e) => {
-            error!("Failed to leave guild {}: {:?}", guild_id, e);
-            false
+        Err(e) => return Err(e),
+    };
+
+    let result = match cli.command {
+        Some(Command::Whoami) => commands::whoami(&client, cli.output).await,
+        Some(Command::Guilds(GuildsCommand::List {
+            filter,
+            sort,
+            columns,
+        })) => commands::list_guilds(&client, filter.as_ref(), sort, &columns, cli.output).await,
+        Some(Command::Guilds(GuildsCommand::Leave { ids, filter, force })) => {
+            commands::leave_guilds(&client, &ids, filter.as_ref(), force, cli.yes, cli.output).await
+        }
+        Some(Command::Guilds(GuildsCommand::Mute {
+            ids,
+            filter,
+            duration,
+        })) => {
+            let action = Action::Mute {
+                until: duration.map(MuteDuration::end_time),
+            };
+            commands::mute_guilds(&client, action, &ids, filter.as_ref(), cli.yes, cli.output).await
+        }
+        Some(Command::Guilds(GuildsCommand::Unmute { ids, filter })) => {
+            commands::mute_guilds(
+                &client,
+                Action::Unmute,
+                &ids,
+                filter.as_ref(),
+                cli.yes,
+                cli.output,
+            )
+            .await
+        }
+        Some(Command::Guilds(GuildsCommand::Activity {
+            inactive_days,
+            leave,
+        })) => commands::guild_activity(&client, inactive_days, leave, cli.yes, cli.output).await,
+        Some(Command::Guilds(GuildsCommand::Prune {
+            min_members,
+            required_permissions,
+            grace_days,
+            blocked_owners,
+            limit,
+        })) => {
+            let criteria = Criteria {
+                min_members,
+                required_permissions,
+                grace_days,
+                blocked_owners,
+            };
+            commands::prune_guilds(&client, &criteria, limit as usize, cli.yes, cli.output).await
+        }
+        Some(Command::Guilds(GuildsCommand::Transfer {
+            guild_id,
+            user_id,
+            mfa_code,
+        })) => {
+            commands::transfer_guild(&client, guild_id, user_id, mfa_code.as_deref(), cli.yes).await
+        }
+        Some(Command::Guilds(GuildsCommand::Delete { guild_id, mfa_code })) => {
+            commands::delete_guild(&client, guild_id, mfa_code.as_deref(), cli.yes).await
+        }
+        #[cfg(feature = "tui")]
+        Some(Command::Tui) => tui::run(&client).await,
+        Some(Command::Resume { run_id, force }) => {
+            commands::resume(&client, &run_id, force, cli.yes, cli.output).await
+        }
+        Some(Command::Allowlist(_) | Command::Vault(_) | Command::Decode { .. }) => {
+            unreachable!("handled before the token is loaded")
+        }
+        None => {
+            let user = client.current_user().await?;
+            info!("Successfully initialized! Dropping to main prompt.");
+            interactive::run(&client, &user)
+                .await
+                .map(|()| Exit::Success)
         }
+    };
+
+    match result {
+        Err(e) if e.status() == Some(StatusCode::UNAUTHORIZED) => {
+            error!("Invalid token provided! Please provide a valid token.");
+            Ok(Exit::InvalidToken)
+        }
+        result => result,
     }
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt::init();
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
 
     let main_span = info_span!("DiscordManager");
     let _main_span_guard = main_span.enter();
 
-    info!("Initializing...");
-
-    let token = std::env::args()
-        .nth(1)
-        .unwrap_or_else(|| std::fs::read_to_string("token.txt").unwrap_or_default());
-
-    if token.trim().is_empty() {
-        error!("No token provided! Please provide a token in token.txt or as an argument.");
-        std::process::exit(1);
-    }
-
-    let token = token.trim();
-    if !check_discord_token(token).await {
-        error!("Invalid token provided! Please provide a valid token.");
-        std::process::exit(1);
-    }
-
-    info!("Successfully initialized! Dropping to main prompt.");
-
-    loop {
-        println!("What would you like to do?");
-        println!("1. Mass leave guilds");
-        println!("2. Exit");
-
-        let mut input = String::new();
-        std::io::stdin().read_line(&mut input).unwrap();
-
-        match input.trim() {
-            "1" => {
-                let guilds = get_guilds(token).await;
-                if guilds.is_empty() {
-                    println!("No guilds found.");
-                    continue;
-                }
-
-                // Ask for each guild if they want to leave
-                for guild in guilds {
-                    println!("Would you like to leave guild {} (y/n)?", guild.name);
-
-                    let mut input = String::new();
-                    std::io::stdin().read_line(&mut input).unwrap();
-
-                    match input.trim() {
-                        "y" => {
-                            if leave_guild(token, &guild.id).await {
-                                println!("Successfully left guild {}!", guild.name);
-                            } else {
-                                println!("Failed to leave guild {}!", guild.name);
-                            }
-                        }
-                        "n" => {
-                            println!("Skipped leaving guild {}.", guild.name);
-                        }
-                        _ => {
-                            println!("Invalid input! Please try again.");
-                        }
-                    }
-                }
-            }
-            "2" => break,
-            _ => println!("Invalid input! Please try again."),
+    match run(cli).await {
+        Ok(exit) => exit.into(),
+        Err(e) => {
+            error!("{}", e);
+            Exit::Failure.into()
         }
     }
-
-    Ok(())
 }
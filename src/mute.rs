@@ -0,0 +1,174 @@
+//! The per-guild notification settings that muting and unmuting send to
+//! `/users/@me/guilds/{guild.id}/settings`.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+
+/// The body of a notification settings update. Only the fields muting
+/// touches are sent, so everything else the user configured is kept.
+#[derive(Debug, Clone, Serialize)]
+pub struct NotificationSettings {
+    pub muted: bool,
+    /// `None` clears the mute's expiry along with the mute itself.
+    pub mute_config: Option<MuteConfig>,
+    pub suppress_everyone: bool,
+    pub suppress_roles: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MuteConfig {
+    /// How long the mute was set for in seconds, or -1 for indefinitely.
+    /// The client shows this as the option that was picked, so it is kept
+    /// to whole minutes.
+    pub selected_time_window: i64,
+    pub end_time: Option<DateTime<Utc>>,
+}
+
+impl NotificationSettings {
+    /// Mutes the guild and its `@everyone` and role mentions, until `until`
+    /// or until it is unmuted.
+    pub fn mute(until: Option<DateTime<Utc>>) -> Self {
+        Self {
+            muted: true,
+            mute_config: Some(MuteConfig {
+                selected_time_window: until.map_or(-1, |until| {
+                    ((until - Utc::now()).num_seconds() + 30).max(0) / 60 * 60
+                }),
+                end_time: until,
+            }),
+            suppress_everyone: true,
+            suppress_roles: true,
+        }
+    }
+
+    pub fn unmute() -> Self {
+        Self {
+            muted: false,
+            mute_config: None,
+            suppress_everyone: false,
+            suppress_roles: false,
+        }
+    }
+}
+
+/// How long to mute for, written as a number and a unit, e.g. `30m`, `8h`,
+/// `7d` or `2w`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MuteDuration(pub TimeDelta);
+
+impl MuteDuration {
+    /// When a mute starting now would end.
+    pub fn end_time(self) -> DateTime<Utc> {
+        Utc::now() + self.0
+    }
+}
+
+impl FromStr for MuteDuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (amount, unit) = s.split_at(split);
+        if amount.is_empty() {
+            return Err(format!("`{}` doesn't start with a number", s));
+        }
+        // Only digits are left, so this can only fail by overflowing.
+        let amount: i64 = amount.parse().map_err(|_| format!("`{}` is too long", s))?;
+        if amount == 0 {
+            return Err("a mute has to last longer than that".to_string());
+        }
+        if unit.is_empty() {
+            return Err(format!("`{}` needs a unit: m, h, d or w", s));
+        }
+
+        let delta = match unit {
+            "m" => TimeDelta::try_minutes(amount),
+            "h" => TimeDelta::try_hours(amount),
+            "d" => TimeDelta::try_days(amount),
+            "w" => TimeDelta::try_weeks(amount),
+            _ => return Err(format!("unknown unit `{}`, expected m, h, d or w", unit)),
+        };
+        delta
+            .map(MuteDuration)
+            .ok_or_else(|| format!("`{}` is too long", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn parse(s: &str) -> Result<TimeDelta, String> {
+        s.parse::<MuteDuration>().map(|duration| duration.0)
+    }
+
+    #[test]
+    fn parses_each_unit() {
+        assert_eq!(parse("30m"), Ok(TimeDelta::minutes(30)));
+        assert_eq!(parse("8h"), Ok(TimeDelta::hours(8)));
+        assert_eq!(parse(" 7d\n"), Ok(TimeDelta::days(7)));
+        assert_eq!(parse("2w"), Ok(TimeDelta::weeks(2)));
+    }
+
+    #[test]
+    fn rejects_bad_durations() {
+        assert_eq!(parse("12").unwrap_err(), "`12` needs a unit: m, h, d or w");
+        assert_eq!(
+            parse("0").unwrap_err(),
+            "a mute has to last longer than that"
+        );
+        assert_eq!(
+            parse("0h").unwrap_err(),
+            "a mute has to last longer than that"
+        );
+        assert_eq!(parse("h").unwrap_err(), "`h` doesn't start with a number");
+        assert_eq!(parse("").unwrap_err(), "`` doesn't start with a number");
+        assert_eq!(
+            parse("3y").unwrap_err(),
+            "unknown unit `y`, expected m, h, d or w"
+        );
+        assert_eq!(
+            parse("99999999999999999999m").unwrap_err(),
+            "`99999999999999999999m` is too long"
+        );
+        assert_eq!(
+            parse("9999999999999w").unwrap_err(),
+            "`9999999999999w` is too long"
+        );
+    }
+
+    #[test]
+    fn indefinite_mute_has_no_time_window() {
+        let settings = serde_json::to_value(NotificationSettings::mute(None)).unwrap();
+        assert_eq!(
+            settings,
+            json!({
+                "muted": true,
+                "mute_config": { "selected_time_window": -1, "end_time": null },
+                "suppress_everyone": true,
+                "suppress_roles": true,
+            })
+        );
+    }
+
+    #[test]
+    fn timed_mute_rounds_to_whole_minutes() {
+        let until = MuteDuration(TimeDelta::hours(8)).end_time();
+        let settings = NotificationSettings::mute(Some(until));
+        let config = settings.mute_config.unwrap();
+        assert_eq!(config.selected_time_window, 8 * 60 * 60);
+        assert_eq!(config.end_time, Some(until));
+    }
+
+    #[test]
+    fn unmute_clears_the_config() {
+        let settings = serde_json::to_value(NotificationSettings::unmute()).unwrap();
+        assert_eq!(settings["muted"], false);
+        assert_eq!(settings["mute_config"], json!(null));
+    }
+}
@@ -0,0 +1,194 @@
+use std::fmt;
+use std::future::Future;
+
+use tracing::{error, warn};
+
+use crate::client::DiscordClient;
+use crate::error::{Result, MFA_REQUIRED};
+use crate::guild::Guild;
+use crate::prompt::{self, read_line};
+use crate::snowflake::Snowflake;
+use crate::user::CurrentUser;
+
+/// The interactive flow for guilds the user owns, which can't simply be left:
+/// they have to be handed over to someone else or deleted.
+pub async fn manage(client: &DiscordClient, user: &CurrentUser) -> Result<()> {
+    let guilds = match client.get_guilds().await {
+        Ok(guilds) => guilds,
+        Err(e) => {
+            error!("Failed to get guilds: {}", e);
+            return Ok(());
+        }
+    };
+    let owned: Vec<Guild> = guilds.into_iter().filter(|guild| guild.owner).collect();
+    if owned.is_empty() {
+        println!("You don't own any guilds.");
+        return Ok(());
+    }
+
+    println!("Which guild would you like to manage?");
+    for (i, guild) in owned.iter().enumerate() {
+        println!("{}. {}", i + 1, guild);
+    }
+    println!("{}. Back", owned.len() + 1);
+    let Some(guild) = pick(owned.len() + 1)?.and_then(|i| owned.get(i)) else {
+        return Ok(());
+    };
+
+    warn_without_mfa(user);
+    println!("What would you like to do with {}?", guild.name);
+    println!("1. Transfer ownership");
+    println!("2. Delete guild");
+    println!("3. Back");
+    match pick(3)? {
+        Some(0) => transfer(client, guild, user.id).await,
+        Some(1) => delete(client, guild).await,
+        _ => Ok(()),
+    }
+}
+
+/// Warns that an owner-only action is only as safe as the token when the
+/// account has no two-factor authentication. Bots have no 2FA of their own.
+pub fn warn_without_mfa(user: &CurrentUser) {
+    if !user.mfa_enabled && !user.bot {
+        warn!(
+            "Two-factor authentication is off for {}, so nothing but the token stands between anyone who has it and your guilds. Consider turning it on.",
+            user
+        );
+    }
+}
+
+/// Reads a 1-based menu choice, returning its 0-based index.
+fn pick(count: usize) -> Result<Option<usize>> {
+    loop {
+        match read_line()?.trim().parse::<usize>() {
+            Ok(n) if (1..=count).contains(&n) => return Ok(Some(n - 1)),
+            _ => println!("Invalid input! Please try again."),
+        }
+    }
+}
+
+async fn transfer(client: &DiscordClient, guild: &Guild, user_id: Snowflake) -> Result<()> {
+    let members = match client.get_guild_members(guild.id).await {
+        Ok(members) => members,
+        Err(e) => {
+            error!("Failed to get members of {}: {}", guild.id, e);
+            return Ok(());
+        }
+    };
+
+    println!("Search for the new owner by name (or press enter to list everyone):");
+    let query = read_line()?.trim().to_lowercase();
+    let candidates: Vec<_> = members
+        .iter()
+        .filter(|member| member.user.as_ref().is_some_and(|user| user.id != user_id))
+        .filter(|member| member.to_string().to_lowercase().contains(&query))
+        .collect();
+    if candidates.is_empty() {
+        println!("No matching members found.");
+        return Ok(());
+    }
+
+    println!("Who should become the new owner?");
+    for (i, member) in candidates.iter().enumerate() {
+        println!("{}. {}", i + 1, member);
+    }
+    println!("{}. Back", candidates.len() + 1);
+    let Some(member) = pick(candidates.len() + 1)?.and_then(|i| candidates.get(i)) else {
+        return Ok(());
+    };
+    let Some(new_owner) = &member.user else {
+        return Ok(());
+    };
+
+    if !prompt::confirm(&format!(
+        "Transfer ownership of {} to {}? You will no longer own it",
+        guild.name, new_owner
+    ))? {
+        return Ok(());
+    }
+
+    let result = with_mfa(|code| async move {
+        client
+            .transfer_ownership(guild, new_owner.id, code.as_deref())
+            .await
+    })
+    .await?;
+    match result {
+        Ok(()) => println!("{}", transferred(client, guild, new_owner)),
+        Err(e) => println!("Failed to transfer {}: {}", guild.name, e),
+    }
+    Ok(())
+}
+
+async fn delete(client: &DiscordClient, guild: &Guild) -> Result<()> {
+    println!(
+        "This will permanently delete {} for everyone. Type the guild's name to confirm:",
+        guild.name
+    );
+    if read_line()?.trim() != guild.name {
+        println!("The name didn't match, so {} was not deleted.", guild.name);
+        return Ok(());
+    }
+
+    let result =
+        with_mfa(|code| async move { client.delete_guild(guild, code.as_deref()).await }).await?;
+    match result {
+        Ok(()) => println!("{}", deleted(client, guild)),
+        Err(e) => println!("Failed to delete {}: {}", guild.name, e),
+    }
+    Ok(())
+}
+
+/// What to tell the user once a guild has been handed over, which says so
+/// when it only would have been.
+pub fn transferred(client: &DiscordClient, guild: &Guild, new_owner: impl fmt::Display) -> String {
+    if client.is_dry_run() {
+        format!(
+            "DRY RUN: would have transferred {} to {}. Nothing was changed.",
+            guild.name, new_owner
+        )
+    } else {
+        format!("{} now owns {}.", new_owner, guild.name)
+    }
+}
+
+/// What to tell the user once a guild has been deleted, which says so when
+/// it only would have been.
+pub fn deleted(client: &DiscordClient, guild: &Guild) -> String {
+    if client.is_dry_run() {
+        format!(
+            "DRY RUN: would have deleted {}. Nothing was changed.",
+            guild.name
+        )
+    } else {
+        format!("Deleted {}.", guild.name)
+    }
+}
+
+/// Runs an owner-only action, asking for a two-factor code and retrying
+/// whenever Discord answers that one is required.
+///
+/// The outer `Result` is for failing to read the prompt, the inner one is the
+/// action's own outcome.
+async fn with_mfa<F, Fut>(action: F) -> Result<Result<()>>
+where
+    F: Fn(Option<String>) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut code = None;
+    loop {
+        match action(code.take()).await {
+            Err(e) if e.code() == Some(MFA_REQUIRED) => {
+                println!("Discord requires two-factor authentication for this.");
+                println!("Enter the code from your authenticator app (or press enter to cancel):");
+                let input = read_line()?;
+                if input.trim().is_empty() {
+                    return Ok(Err(e));
+                }
+                code = Some(input.trim().to_string());
+            }
+            result => return Ok(result),
+        }
+    }
+}
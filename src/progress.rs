@@ -0,0 +1,88 @@
+//! A live progress line for bulk operations, redrawn in place on stderr.
+
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+pub struct Progress {
+    verb: &'static str,
+    total: usize,
+    done: usize,
+    failed: usize,
+    started: Instant,
+    /// Only draw when stderr is a terminal, so redirected logs stay clean.
+    live: bool,
+}
+
+impl Progress {
+    pub fn new(verb: &'static str, total: usize) -> Self {
+        Self {
+            verb,
+            total,
+            done: 0,
+            failed: 0,
+            started: Instant::now(),
+            live: io::stderr().is_terminal(),
+        }
+    }
+
+    pub fn succeeded(&mut self) {
+        self.done += 1;
+    }
+
+    pub fn failed(&mut self) {
+        self.failed += 1;
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.total - self.done - self.failed
+    }
+
+    /// Redraws the line. `rate_limit_eta` is how long the rate limits say the
+    /// remaining requests will take; the pace so far is used as a floor,
+    /// since it accounts for latency the limits don't.
+    pub fn draw(&self, rate_limit_eta: Option<Duration>) {
+        if !self.live {
+            return;
+        }
+
+        let finished = (self.done + self.failed) as u32;
+        let pace =
+            (finished > 0).then(|| self.started.elapsed() / finished * self.remaining() as u32);
+        let eta = match (rate_limit_eta, pace) {
+            (Some(limits), Some(pace)) => Some(limits.max(pace)),
+            (eta, pace) => eta.or(pace),
+        };
+
+        // Leave the cursor at the start of the line so that any log line
+        // printed meanwhile overwrites it rather than being appended to it.
+        let mut stderr = io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\x1b[2K{} guilds: {} done, {} failed, {} remaining, ETA {}\r",
+            self.verb,
+            self.done,
+            self.failed,
+            self.remaining(),
+            eta.map_or_else(|| "unknown".to_string(), format_duration)
+        );
+        let _ = stderr.flush();
+    }
+
+    /// Clears the line.
+    pub fn finish(&self) {
+        if self.live {
+            let mut stderr = io::stderr().lock();
+            let _ = write!(stderr, "\x1b[2K");
+            let _ = stderr.flush();
+        }
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
@@ -0,0 +1,39 @@
+use std::io::{self, Write};
+
+use crate::error::Result;
+
+/// Reads one line from stdin, treating end of input as an error so prompt
+/// loops can't spin forever on a closed stdin.
+pub fn read_line() -> Result<String> {
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input)? == 0 {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(input)
+}
+
+/// Asks a yes/no question until it gets an answer.
+pub fn confirm(question: &str) -> Result<bool> {
+    confirm_to(&mut io::stdout(), question)
+}
+
+/// Like [`confirm`], but asks on `out`, e.g. stderr while stdout is
+/// reserved for machine-readable output.
+pub fn confirm_to(out: &mut dyn Write, question: &str) -> Result<bool> {
+    loop {
+        writeln!(out, "{} (y/n)?", question)?;
+        out.flush()?;
+        match read_line()?.trim() {
+            "y" => return Ok(true),
+            "n" => return Ok(false),
+            _ => writeln!(out, "Invalid input! Please try again.")?,
+        }
+    }
+}
+
+/// Asks for a secret on the terminal without echoing what is typed.
+pub fn secret(question: &str) -> Result<String> {
+    Ok(rpassword::prompt_password(format!("{}: ", question))?)
+}
@@ -0,0 +1,160 @@
+//! Picks out the guilds a bot has no business staying in: ones too small to
+//! matter, ones that never granted the permissions it needs, and ones owned
+//! by blocked users.
+
+use std::fmt;
+
+use chrono::Utc;
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::client::DiscordClient;
+use crate::error::Result;
+use crate::guild::{Guild, Permissions};
+use crate::progress::Progress;
+use crate::snowflake::Snowflake;
+
+/// What makes a guild worth leaving. A guild is pruned if it meets any of
+/// the criteria that are set.
+#[derive(Debug, Clone, Default)]
+pub struct Criteria {
+    pub min_members: Option<u64>,
+    pub required_permissions: Option<Permissions>,
+    /// How long a guild gets to grant the required permissions after the
+    /// account joins it.
+    pub grace_days: u32,
+    pub blocked_owners: Vec<Snowflake>,
+}
+
+/// Why a guild is being pruned.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum Reason {
+    FewMembers {
+        members: u64,
+        min: u64,
+    },
+    MissingPermissions {
+        missing: Vec<String>,
+        joined_days_ago: Option<i64>,
+    },
+    BlockedOwner {
+        owner_id: Snowflake,
+    },
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::FewMembers { members, min } => {
+                write!(f, "{} members, fewer than {}", members, min)
+            }
+            Reason::MissingPermissions {
+                missing,
+                joined_days_ago,
+            } => {
+                write!(f, "missing {}", missing.join(", "))?;
+                if let Some(days) = joined_days_ago {
+                    write!(f, " {} days after joining", days)?;
+                }
+                Ok(())
+            }
+            Reason::BlockedOwner { owner_id } => write!(f, "owned by blocked user {}", owner_id),
+        }
+    }
+}
+
+/// A guild that meets at least one of the criteria.
+#[derive(Debug, Clone)]
+pub struct Candidate<'a> {
+    pub guild: &'a Guild,
+    pub reasons: Vec<Reason>,
+}
+
+/// Checks every guild against `criteria`, keeping their order. The join
+/// date and owner take a request per guild, so they are only fetched for
+/// guilds no cheaper check has already condemned.
+pub async fn candidates<'a>(
+    client: &DiscordClient,
+    guilds: &'a [Guild],
+    criteria: &Criteria,
+) -> Result<Vec<Candidate<'a>>> {
+    let mut progress = Progress::new("Checking", guilds.len());
+    let mut checked = stream::iter(guilds)
+        .map(|guild| async {
+            Candidate {
+                guild,
+                reasons: reasons(client, guild, criteria).await,
+            }
+        })
+        .buffered(client.concurrency());
+
+    let mut candidates = Vec::new();
+    while let Some(candidate) = checked.next().await {
+        progress.succeeded();
+        progress.draw(None);
+        if !candidate.reasons.is_empty() {
+            candidates.push(candidate);
+        }
+    }
+    progress.finish();
+    Ok(candidates)
+}
+
+async fn reasons(client: &DiscordClient, guild: &Guild, criteria: &Criteria) -> Vec<Reason> {
+    let mut reasons = Vec::new();
+
+    if let (Some(min), Some(members)) = (criteria.min_members, guild.approximate_member_count) {
+        if members < min {
+            reasons.push(Reason::FewMembers { members, min });
+        }
+    }
+
+    if let Some(required) = criteria.required_permissions {
+        let missing = guild.permissions.missing(required);
+        if !missing.is_empty() {
+            if criteria.grace_days == 0 {
+                reasons.push(Reason::MissingPermissions {
+                    missing: missing.names(),
+                    joined_days_ago: None,
+                });
+            } else if reasons.is_empty() {
+                match client.current_member(guild.id).await {
+                    Ok(member) => {
+                        let days = member
+                            .joined_at
+                            .map(|joined_at| (Utc::now() - joined_at).num_days());
+                        if days.is_some_and(|days| days > criteria.grace_days.into()) {
+                            reasons.push(Reason::MissingPermissions {
+                                missing: missing.names(),
+                                joined_days_ago: days,
+                            });
+                        }
+                    }
+                    Err(e) => warn!(
+                        "Couldn't tell when {} ({}) was joined, so not pruning it for its permissions: {}",
+                        guild.name, guild.id, e
+                    ),
+                }
+            }
+        }
+    }
+
+    if !criteria.blocked_owners.is_empty() && reasons.is_empty() {
+        match client.get_guild(guild.id).await {
+            Ok(details) if criteria.blocked_owners.contains(&details.owner_id) => {
+                reasons.push(Reason::BlockedOwner {
+                    owner_id: details.owner_id,
+                });
+            }
+            Ok(_) => {}
+            Err(e) => warn!(
+                "Couldn't tell who owns {} ({}), so not pruning it for its owner: {}",
+                guild.name, guild.id, e
+            ),
+        }
+    }
+
+    reasons
+}
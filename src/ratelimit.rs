@@ -0,0 +1,476 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::header::HeaderMap;
+use reqwest::Method;
+use serde::Deserialize;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+use tracing::{debug, warn};
+
+const BUCKET: &str = "x-ratelimit-bucket";
+const LIMIT: &str = "x-ratelimit-limit";
+const REMAINING: &str = "x-ratelimit-remaining";
+const RESET_AFTER: &str = "x-ratelimit-reset-after";
+const GLOBAL: &str = "x-ratelimit-global";
+const RETRY_AFTER: &str = "retry-after";
+
+/// How long to back off after a 429 that doesn't say, or says something
+/// that isn't a usable duration.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Longer than any rate limit Discord hands out.
+const MAX_WAIT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Turns seconds from a header or body into a duration. `inf`, NaN and
+/// anything past [`MAX_WAIT`] are rejected rather than risk overflowing the
+/// `Instant`s and estimates computed from them.
+fn seconds(secs: f64) -> Option<Duration> {
+    // `max` would turn NaN into zero, so it has to be caught first.
+    if secs.is_nan() {
+        return None;
+    }
+    Duration::try_from_secs_f64(secs.max(0.0))
+        .ok()
+        .filter(|duration| *duration <= MAX_WAIT)
+}
+
+/// The body Discord sends with a 429.
+#[derive(Debug, Deserialize)]
+pub struct RateLimited {
+    pub retry_after: f64,
+    #[serde(default)]
+    pub global: bool,
+}
+
+/// Identifies which rate-limit bucket a request falls into.
+///
+/// Discord only tells us the bucket hash after the first response, so until
+/// then requests are grouped by method and path with IDs stripped. Top-level
+/// guild and channel IDs are "major parameters" and always split buckets.
+#[derive(Debug, Clone)]
+pub struct Route {
+    key: String,
+    major: String,
+}
+
+impl Route {
+    pub fn new(method: &Method, path: &str) -> Self {
+        let mut segments = path.split('/').filter(|s| !s.is_empty()).peekable();
+        let mut key = method.to_string();
+        let mut major = String::new();
+        let mut first = true;
+
+        while let Some(segment) = segments.next() {
+            key.push('/');
+            let is_id = segment.chars().all(|c| c.is_ascii_digit());
+            if is_id && !first {
+                key.push_str(":id");
+            } else {
+                key.push_str(segment);
+            }
+            if first && matches!(segment, "guilds" | "channels" | "webhooks") {
+                if let Some(id) = segments
+                    .peek()
+                    .filter(|s| s.chars().all(|c| c.is_ascii_digit()))
+                {
+                    major = id.to_string();
+                    key.push('/');
+                    key.push_str(id);
+                    segments.next();
+                }
+            }
+            first = false;
+        }
+
+        Self { key, major }
+    }
+}
+
+#[derive(Debug, Default)]
+struct BucketState {
+    limit: Option<u32>,
+    remaining: Option<u32>,
+    reset_at: Option<Instant>,
+    /// The longest reset-after seen, as a stand-in for the window length.
+    window: Option<Duration>,
+}
+
+impl BucketState {
+    /// Requests left in the current window, assuming a fresh window once the
+    /// last one has reset.
+    fn available(&self, now: Instant) -> Option<u32> {
+        match self.reset_at {
+            Some(reset_at) if reset_at <= now => self.limit,
+            _ => self.remaining,
+        }
+    }
+}
+
+/// A rate-limit bucket. While its limits are known, requests draw from
+/// `remaining` and run side by side; otherwise they take turns holding
+/// `exclusive` until a response says what the limits are.
+#[derive(Debug, Default)]
+pub struct Bucket {
+    state: Mutex<BucketState>,
+    exclusive: Arc<AsyncMutex<()>>,
+}
+
+/// Permission to send one request on a bucket.
+pub struct Ticket {
+    bucket: Arc<Bucket>,
+    _exclusive: Option<OwnedMutexGuard<()>>,
+}
+
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    routes: Mutex<HashMap<String, String>>,
+    buckets: Mutex<HashMap<String, Arc<Bucket>>>,
+    global_reset: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    fn bucket_key(&self, route: &Route) -> String {
+        match self.routes.lock().unwrap().get(&route.key) {
+            Some(hash) => format!("{}:{}", hash, route.major),
+            None => route.key.clone(),
+        }
+    }
+
+    fn bucket_for(&self, route: &Route) -> Arc<Bucket> {
+        let key = self.bucket_key(route);
+        self.buckets.lock().unwrap().entry(key).or_default().clone()
+    }
+
+    /// Takes a request's worth of the bucket's remaining capacity, or says
+    /// how long until there is some. `None` means the limits aren't known.
+    fn reserve(bucket: &Bucket) -> Option<std::result::Result<(), Duration>> {
+        let mut state = bucket.state.lock().unwrap();
+        let now = Instant::now();
+        match state.available(now)? {
+            0 => state
+                .reset_at
+                .and_then(|reset_at| reset_at.checked_duration_since(now))
+                .map(Err),
+            available => {
+                if state.reset_at.is_some_and(|reset_at| reset_at <= now) {
+                    state.reset_at = None;
+                }
+                state.remaining = Some(available - 1);
+                Some(Ok(()))
+            }
+        }
+    }
+
+    /// Waits until a request on `route` is allowed to go out.
+    pub async fn acquire(&self, route: &Route) -> Ticket {
+        let bucket = self.bucket_for(route);
+
+        let exclusive = loop {
+            match Self::reserve(&bucket) {
+                Some(Ok(())) => break None,
+                Some(Err(wait)) => {
+                    debug!("Bucket {} exhausted, waiting {:?}", route.key, wait);
+                    tokio::time::sleep(wait).await;
+                }
+                None => {
+                    let guard = bucket.exclusive.clone().lock_owned().await;
+                    // Whoever held it before may have learned the limits, in
+                    // which case this reserves a slot like the first check.
+                    match Self::reserve(&bucket) {
+                        None => break Some(guard),
+                        Some(Ok(())) => break None,
+                        Some(Err(wait)) => {
+                            drop(guard);
+                            debug!("Bucket {} exhausted, waiting {:?}", route.key, wait);
+                            tokio::time::sleep(wait).await;
+                        }
+                    }
+                }
+            }
+        };
+
+        let global_wait = self
+            .global_reset
+            .lock()
+            .unwrap()
+            .and_then(|reset_at| reset_at.checked_duration_since(Instant::now()));
+        if let Some(wait) = global_wait {
+            debug!("Globally rate limited, waiting {:?}", wait);
+            tokio::time::sleep(wait).await;
+        }
+
+        Ticket {
+            bucket,
+            _exclusive: exclusive,
+        }
+    }
+
+    /// Records the rate-limit headers of a response against the bucket it
+    /// was sent on.
+    pub fn update(&self, route: &Route, ticket: &mut Ticket, headers: &HeaderMap) {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+        if let Some(hash) = header(BUCKET) {
+            let known = self
+                .routes
+                .lock()
+                .unwrap()
+                .insert(route.key.clone(), hash.to_string());
+            if known.as_deref() != Some(hash) {
+                self.buckets
+                    .lock()
+                    .unwrap()
+                    .entry(format!("{}:{}", hash, route.major))
+                    .or_insert_with(|| ticket.bucket.clone());
+            }
+        }
+
+        let mut state = ticket.bucket.state.lock().unwrap();
+        let now = Instant::now();
+        if let Some(limit) = header(LIMIT).and_then(|v| v.parse().ok()) {
+            state.limit = Some(limit);
+        }
+        if let Some(remaining) = header(REMAINING).and_then(|v| v.parse::<u32>().ok()) {
+            // Other requests may have gone out since this one, so within the
+            // same window only ever count down.
+            state.remaining = match (state.remaining, state.reset_at) {
+                (Some(current), Some(reset_at)) if reset_at > now => Some(current.min(remaining)),
+                _ => Some(remaining),
+            };
+        }
+        if let Some(reset_after) = header(RESET_AFTER)
+            .and_then(|v| v.parse().ok())
+            .and_then(seconds)
+        {
+            state.reset_at = Some(now + reset_after);
+            state.window = state.window.max(Some(reset_after));
+        }
+    }
+
+    /// Estimates how long `requests` more requests on `route` will take to
+    /// get through its bucket, going by the last rate-limit headers seen.
+    /// `None` until a response on the route has carried them.
+    pub fn estimate(&self, route: &Route, requests: u32) -> Option<Duration> {
+        let bucket = self
+            .buckets
+            .lock()
+            .unwrap()
+            .get(&self.bucket_key(route))
+            .cloned()?;
+        let state = bucket.state.lock().unwrap();
+        let now = Instant::now();
+
+        let (limit, window) = (state.limit?, state.window?);
+        let available = state.available(now)?;
+        if requests <= available || limit == 0 {
+            return Some(Duration::ZERO);
+        }
+
+        let until_reset = state.reset_at.map_or(Duration::ZERO, |reset_at| {
+            reset_at.saturating_duration_since(now)
+        });
+        let later_windows = (requests - available - 1) / limit;
+        Some(until_reset + window * later_windows)
+    }
+
+    /// Works out how long to back off after a 429, marking the whole client
+    /// as blocked when Discord says the limit is global.
+    pub fn rate_limited(&self, headers: &HeaderMap, body: Option<&RateLimited>) -> Duration {
+        let retry_after = body.map(|b| b.retry_after).or_else(|| {
+            headers
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+        });
+        let wait = retry_after.and_then(seconds).unwrap_or(DEFAULT_RETRY_AFTER);
+
+        let global = body.is_some_and(|b| b.global) || headers.contains_key(GLOBAL);
+        if global {
+            warn!(
+                "Hit the global rate limit, pausing all requests for {:?}",
+                wait
+            );
+            *self.global_reset.lock().unwrap() = Some(Instant::now() + wait);
+        } else {
+            warn!("Rate limited, retrying in {:?}", wait);
+        }
+        wait
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn route() -> Route {
+        Route::new(&Method::GET, "/guilds/1/channels")
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    /// Sends one request on `route` and records `pairs` as its response.
+    async fn respond(limiter: &RateLimiter, route: &Route, pairs: &[(&'static str, &'static str)]) {
+        let mut ticket = limiter.acquire(route).await;
+        limiter.update(route, &mut ticket, &headers(pairs));
+    }
+
+    fn remaining(limiter: &RateLimiter, route: &Route) -> Option<u32> {
+        limiter.bucket_for(route).state.lock().unwrap().remaining
+    }
+
+    #[tokio::test]
+    async fn exhausted_bucket_waits_for_the_reset() {
+        let limiter = RateLimiter::default();
+        let route = route();
+        respond(
+            &limiter,
+            &route,
+            &[(LIMIT, "2"), (REMAINING, "0"), (RESET_AFTER, "0.2")],
+        )
+        .await;
+
+        let start = Instant::now();
+        let ticket = limiter.acquire(&route).await;
+        assert!(start.elapsed() >= Duration::from_millis(150));
+        assert!(ticket._exclusive.is_none());
+        // The fresh window's limit, less the request that just went out.
+        assert_eq!(remaining(&limiter, &route), Some(1));
+        assert_eq!(
+            limiter.bucket_for(&route).state.lock().unwrap().reset_at,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_limits_take_turns() {
+        let limiter = RateLimiter::default();
+        let route = route();
+
+        let mut first = limiter.acquire(&route).await;
+        assert!(first._exclusive.is_some());
+
+        let second = limiter.acquire(&route);
+        tokio::pin!(second);
+        assert!(tokio::time::timeout(Duration::from_millis(50), &mut second)
+            .await
+            .is_err());
+
+        // Once the first response says what the limits are, the waiting
+        // request reserves a slot instead of taking its turn alone.
+        limiter.update(
+            &route,
+            &mut first,
+            &headers(&[(LIMIT, "5"), (REMAINING, "4"), (RESET_AFTER, "10")]),
+        );
+        drop(first);
+        let second = second.await;
+        assert!(second._exclusive.is_none());
+        assert_eq!(remaining(&limiter, &route), Some(3));
+    }
+
+    #[tokio::test]
+    async fn waiting_request_rechecks_an_exhausted_bucket() {
+        let limiter = RateLimiter::default();
+        let route = route();
+
+        let mut first = limiter.acquire(&route).await;
+        let second = limiter.acquire(&route);
+        tokio::pin!(second);
+        assert!(tokio::time::timeout(Duration::from_millis(50), &mut second)
+            .await
+            .is_err());
+
+        limiter.update(
+            &route,
+            &mut first,
+            &headers(&[(LIMIT, "1"), (REMAINING, "0"), (RESET_AFTER, "0.2")]),
+        );
+        let start = Instant::now();
+        drop(first);
+        let second = second.await;
+        assert!(start.elapsed() >= Duration::from_millis(150));
+        assert!(second._exclusive.is_none());
+        assert_eq!(remaining(&limiter, &route), Some(0));
+    }
+
+    #[test]
+    fn unusable_retry_after_falls_back_to_the_default() {
+        let limiter = RateLimiter::default();
+        for value in ["inf", "NaN", "1e300", "nonsense"] {
+            let wait = limiter.rate_limited(&headers(&[(RETRY_AFTER, value)]), None);
+            assert_eq!(wait, DEFAULT_RETRY_AFTER, "Retry-After: {}", value);
+        }
+        for retry_after in [f64::INFINITY, f64::NAN, 1e300] {
+            let body = RateLimited {
+                retry_after,
+                global: true,
+            };
+            assert_eq!(
+                limiter.rate_limited(&HeaderMap::new(), Some(&body)),
+                DEFAULT_RETRY_AFTER
+            );
+        }
+        assert_eq!(
+            limiter.rate_limited(&headers(&[(RETRY_AFTER, "2.5")]), None),
+            Duration::from_millis(2500)
+        );
+    }
+
+    #[tokio::test]
+    async fn infinite_reset_after_is_ignored() {
+        let limiter = RateLimiter::default();
+        let route = route();
+        respond(
+            &limiter,
+            &route,
+            &[(LIMIT, "1"), (REMAINING, "0"), (RESET_AFTER, "inf")],
+        )
+        .await;
+
+        let bucket = limiter.bucket_for(&route);
+        let state = bucket.state.lock().unwrap();
+        assert_eq!(state.reset_at, None);
+        assert_eq!(state.window, None);
+    }
+
+    #[tokio::test]
+    async fn estimate_spans_windows() {
+        let limiter = RateLimiter::default();
+        let route = route();
+        assert_eq!(limiter.estimate(&route, 10), None);
+
+        respond(
+            &limiter,
+            &route,
+            &[(LIMIT, "5"), (REMAINING, "2"), (RESET_AFTER, "10")],
+        )
+        .await;
+        let window = Duration::from_secs(10);
+        let close = |estimate: Option<Duration>, expected: Duration| {
+            let estimate = estimate.unwrap();
+            assert!(
+                estimate <= expected && estimate + Duration::from_secs(1) > expected,
+                "{:?} is not close to {:?}",
+                estimate,
+                expected
+            );
+        };
+
+        assert_eq!(limiter.estimate(&route, 2), Some(Duration::ZERO));
+        // The third request waits for the reset, and the next five fit in
+        // the window after it.
+        close(limiter.estimate(&route, 3), window);
+        close(limiter.estimate(&route, 7), window);
+        close(limiter.estimate(&route, 8), window * 2);
+        close(limiter.estimate(&route, 13), window * 3);
+    }
+}
@@ -0,0 +1,180 @@
+//! Discord's 64-bit IDs, which encode when and where they were generated.
+//!
+//! ```text
+//!  63                     22 21    17 16    12 11         0
+//! | ms since Discord epoch | worker | process | increment |
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The first millisecond of 2015, which snowflake timestamps count from.
+pub const DISCORD_EPOCH_MS: i64 = 1_420_070_400_000;
+
+/// How far in the future an ID may claim to have been created, to allow for
+/// clock skew, before it is rejected as a typo.
+const MAX_SKEW: Duration = Duration::days(1);
+
+/// A Discord ID. Sent and stored as a string, like Discord does, but ordered
+/// numerically, which is also the order the IDs were created in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Snowflake(pub u64);
+
+impl Snowflake {
+    pub fn created_at(self) -> DateTime<Utc> {
+        DateTime::from_timestamp_millis(DISCORD_EPOCH_MS + (self.0 >> 22) as i64)
+            .expect("42-bit timestamps are always in range")
+    }
+
+    pub fn worker_id(self) -> u8 {
+        ((self.0 >> 17) & 0x1f) as u8
+    }
+
+    pub fn process_id(self) -> u8 {
+        ((self.0 >> 12) & 0x1f) as u8
+    }
+
+    /// Counts the IDs generated in the same millisecond by the same process.
+    pub fn increment(self) -> u16 {
+        (self.0 & 0xfff) as u16
+    }
+}
+
+impl fmt::Display for Snowflake {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub input: String,
+    pub message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` is not a Discord ID: {}", self.input, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Accepts only what Discord could have issued: digits, fitting in 64 bits,
+/// and not created in the future.
+impl FromStr for Snowflake {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |message| ParseError {
+            input: s.to_string(),
+            message,
+        };
+
+        let digits = s.trim();
+        if digits.is_empty() {
+            return Err(error("it is empty"));
+        }
+        if !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(error("IDs are made of digits only"));
+        }
+        let id = digits
+            .parse()
+            .map(Snowflake)
+            .map_err(|_| error("it is too large"))?;
+        if id.0 == 0 {
+            return Err(error("IDs are never 0"));
+        }
+        if id.created_at() > Utc::now() + MAX_SKEW {
+            return Err(error("it would have been created in the future"));
+        }
+        Ok(id)
+    }
+}
+
+impl Serialize for Snowflake {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Takes the strings Discord sends and this tool writes. Unlike parsing
+/// user input, IDs created in the "future" are accepted, since clock skew
+/// shouldn't make a stored ID unreadable.
+impl<'de> Deserialize<'de> for Snowflake {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SnowflakeVisitor;
+
+        impl Visitor<'_> for SnowflakeVisitor {
+            type Value = Snowflake;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a Discord ID")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Snowflake, E> {
+                v.parse().map(Snowflake).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(SnowflakeVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: Snowflake = Snowflake(175928847299117063);
+
+    fn error(input: &str) -> &'static str {
+        input.parse::<Snowflake>().unwrap_err().message
+    }
+
+    #[test]
+    fn decodes_the_documented_example() {
+        assert_eq!(
+            serde_json::to_value(ID.created_at()).unwrap(),
+            "2016-04-30T11:18:25.796Z"
+        );
+        assert_eq!(ID.worker_id(), 1);
+        assert_eq!(ID.process_id(), 0);
+        assert_eq!(ID.increment(), 7);
+    }
+
+    #[test]
+    fn parses_and_round_trips() {
+        assert_eq!(" 175928847299117063\n".parse(), Ok(ID));
+        let json = serde_json::to_string(&ID).unwrap();
+        assert_eq!(json, "\"175928847299117063\"");
+        assert_eq!(serde_json::from_str::<Snowflake>(&json).unwrap(), ID);
+    }
+
+    #[test]
+    fn rejects_what_discord_never_issues() {
+        assert_eq!(error(""), "it is empty");
+        assert_eq!(error("  "), "it is empty");
+        assert_eq!(error("-1"), "IDs are made of digits only");
+        assert_eq!(error("12a4"), "IDs are made of digits only");
+        assert_eq!(error("18446744073709551616"), "it is too large");
+        assert_eq!(error("0"), "IDs are never 0");
+        assert_eq!(
+            error(&u64::MAX.to_string()),
+            "it would have been created in the future"
+        );
+    }
+
+    #[test]
+    fn stored_future_ids_still_deserialize() {
+        let id = format!("\"{}\"", u64::MAX);
+        assert_eq!(
+            serde_json::from_str::<Snowflake>(&id).unwrap(),
+            Snowflake(u64::MAX)
+        );
+        assert!(serde_json::from_str::<Snowflake>("175928847299117063").is_err());
+    }
+}
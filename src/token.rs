@@ -0,0 +1,317 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::str::FromStr;
+
+use reqwest::header::HeaderValue;
+use tracing::warn;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::error::Result;
+use crate::prompt;
+
+/// How many leading characters of a token are safe to show. Discord user
+/// tokens start with the base64 user ID, so this identifies the account
+/// without revealing anything secret.
+const VISIBLE_PREFIX: usize = 4;
+
+/// The prefix Discord expects in front of bot tokens.
+const BOT_PREFIX: &str = "Bot ";
+
+/// A Discord token. It is redacted when formatted and wiped from memory when
+/// dropped, and [`Token::authorization`] is the only way to build a header
+/// from it.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Token {
+    secret: String,
+    bot: bool,
+}
+
+impl Token {
+    /// Takes ownership of a token, trimming surrounding whitespace such as a
+    /// trailing newline from a file. A leading `Bot ` marks a bot token.
+    pub fn new(mut token: String) -> Self {
+        let trimmed = token.trim();
+        let (secret, bot) = match trimmed.strip_prefix(BOT_PREFIX) {
+            Some(secret) => (secret.trim_start(), true),
+            None => (trimmed, false),
+        };
+        let parsed = Self {
+            secret: secret.to_string(),
+            bot,
+        };
+        token.zeroize();
+        parsed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.secret.is_empty()
+    }
+
+    pub fn is_bot(&self) -> bool {
+        self.bot
+    }
+
+    /// The same token, sent as a bot token.
+    pub fn to_bot(&self) -> Self {
+        Self {
+            secret: self.secret.clone(),
+            bot: true,
+        }
+    }
+
+    /// The value of the `Authorization` header, marked sensitive so that
+    /// `reqwest` and `hyper` never log it.
+    pub fn authorization(&self) -> HeaderValue {
+        let value = Zeroizing::new(self.expose());
+        let mut value =
+            HeaderValue::from_str(&value).unwrap_or_else(|_| HeaderValue::from_static("invalid"));
+        value.set_sensitive(true);
+        value
+    }
+
+    /// The token as it would be typed, with the `Bot ` prefix for bots, for
+    /// encrypting it into the vault. Never format or log this.
+    pub fn expose(&self) -> String {
+        if self.bot {
+            format!("{}{}", BOT_PREFIX, self.secret)
+        } else {
+            self.secret.clone()
+        }
+    }
+
+    fn redacted(&self) -> String {
+        let prefix: String = self.secret.chars().take(VISIBLE_PREFIX).collect();
+        if self.bot {
+            format!("{}{}…", BOT_PREFIX, prefix)
+        } else {
+            format!("{}…", prefix)
+        }
+    }
+}
+
+/// Where a token was found, for logging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Argument,
+    Stdin,
+    File,
+    Env,
+    Vault,
+    Prompt,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::Argument => "the command line",
+            Source::Stdin => "stdin",
+            Source::File => "the token file",
+            Source::Env => ENV_VAR,
+            Source::Vault => "the vault",
+            Source::Prompt => "the prompt",
+        })
+    }
+}
+
+/// The environment variable a token can be passed in.
+pub const ENV_VAR: &str = "DISCORD_TOKEN";
+
+/// The file read when no other source gives a token, relative to the
+/// working directory.
+pub const LEGACY_FILE: &str = "token.txt";
+
+/// Reads the first line of stdin, leaving the rest for any prompts. On a
+/// terminal it is read without echoing, like the prompt.
+pub fn from_stdin() -> Result<Token> {
+    if io::stdin().is_terminal() {
+        return Ok(Token::new(prompt::secret("Discord token")?));
+    }
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    Ok(Token::new(line))
+}
+
+/// Reads a token file, warning if other users can read it too.
+pub fn from_file(path: &Path) -> Result<Token> {
+    let token = Token::new(fs::read_to_string(path)?);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = fs::metadata(path)?.permissions().mode();
+        if mode & 0o077 != 0 {
+            warn!(
+                "{} can be read by other users (mode {:o}). Restrict it with `chmod 600 {}`.",
+                path.display(),
+                mode & 0o777,
+                path.display()
+            );
+        }
+    }
+
+    Ok(token)
+}
+
+/// Reads `DISCORD_TOKEN`, if it's set.
+pub fn from_env() -> Option<Token> {
+    std::env::var(ENV_VAR)
+        .ok()
+        .map(Token::new)
+        .filter(|token| !token.is_empty())
+}
+
+/// Asks for the token without echoing it, if there is a terminal to ask on.
+pub fn from_prompt() -> Result<Option<Token>> {
+    if !io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    Ok(Some(Token::new(prompt::secret("Discord token")?)))
+}
+
+impl FromStr for Token {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(Self::new(s.to_string()))
+    }
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Token").field(&self.redacted()).finish()
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.redacted())
+    }
+}
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tracing::{debug, error, info, trace, warn, Level};
+
+    use super::*;
+    use crate::client::DiscordClient;
+    use crate::guild::{Guild, Permissions};
+    use crate::snowflake::Snowflake;
+
+    const RAW: &str = "MTIzNDU2Nzg5MDEyMzQ1Njc4.Gabcde.secret-part-of-the-token";
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Answers every request with a 401, or a 429 the first time, so that
+    /// both the error and the retry paths get logged.
+    async fn serve(listener: TcpListener) {
+        let mut first = true;
+        loop {
+            let Ok((mut socket, _)) = listener.accept().await else {
+                return;
+            };
+            let mut buf = [0; 4096];
+            let _ = socket.read(&mut buf).await;
+            let (status, body) = if std::mem::take(&mut first) {
+                (
+                    "429 Too Many Requests",
+                    r#"{"retry_after":0.01,"global":false}"#,
+                )
+            } else {
+                (
+                    "401 Unauthorized",
+                    r#"{"message":"401: Unauthorized","code":0}"#,
+                )
+            };
+            let response = format!(
+                "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_never_contain_the_raw_token() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        tracing_subscriber::fmt()
+            .with_max_level(Level::TRACE)
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .try_init()
+            .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/api", listener.local_addr().unwrap());
+        tokio::spawn(serve(listener));
+
+        let token = Token::new(format!("  {}\n", RAW));
+        trace!("token: {:?}", token);
+        debug!("token: {}", token);
+        info!(?token, "structured");
+        warn!(%token, "structured");
+
+        let client = DiscordClient::builder(token)
+            .base_url(base_url)
+            .build()
+            .unwrap();
+        let guild = Guild {
+            id: Snowflake(1),
+            name: "Guild".to_string(),
+            icon: None,
+            owner: false,
+            permissions: Permissions(0),
+            features: Vec::new(),
+            approximate_member_count: None,
+            approximate_presence_count: None,
+            member: None,
+            activity: None,
+        };
+        let err = client.leave_guild(&guild).await.unwrap_err();
+        error!("{} / {:?}", err, err);
+        let err = client.check_discord_token().await.unwrap_err();
+        error!("{} / {:?}", err, err);
+
+        let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(
+            logs.contains("MTIz…"),
+            "expected redacted tokens in:\n{}",
+            logs
+        );
+        assert!(!logs.contains(RAW), "raw token leaked into:\n{}", logs);
+        assert!(
+            !logs.contains("secret-part"),
+            "token leaked into:\n{}",
+            logs
+        );
+    }
+}
@@ -0,0 +1,598 @@
+//! A full-screen guild browser for picking guilds to leave, built with the
+//! `tui` feature.
+
+use std::collections::HashSet;
+use std::future::Future;
+
+use futures::stream::{self, StreamExt};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Cell, List, ListItem, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::cli::{Exit, OutputFormat};
+use crate::client::DiscordClient;
+use crate::commands::{self, Action};
+use crate::error::Result;
+use crate::guild::Guild;
+use crate::journal::{PlannedGuild, RunLog};
+use crate::snowflake::Snowflake;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortKey {
+    Name,
+    Members,
+    Online,
+    Joined,
+    Id,
+}
+
+impl SortKey {
+    fn next(self) -> Self {
+        match self {
+            SortKey::Name => SortKey::Members,
+            SortKey::Members => SortKey::Online,
+            SortKey::Online => SortKey::Joined,
+            SortKey::Joined => SortKey::Id,
+            SortKey::Id => SortKey::Name,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Members => "members",
+            SortKey::Online => "online",
+            SortKey::Joined => "joined",
+            SortKey::Id => "id",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Browse,
+    Search,
+    Confirm,
+}
+
+struct App<'a> {
+    client: &'a DiscordClient,
+    guilds: Vec<Guild>,
+    /// Indices into `guilds` that match the search, in display order.
+    view: Vec<usize>,
+    table: TableState,
+    selected: HashSet<Snowflake>,
+    /// Where the last selection toggle happened, for range selection.
+    anchor: Option<usize>,
+    query: String,
+    sort: SortKey,
+    descending: bool,
+    mode: Mode,
+    /// Whether the details pane is open. Its membership section takes a
+    /// request per guild, so it starts closed.
+    details: bool,
+    /// Guilds whose membership has been asked for, whether or not that
+    /// worked, so failures aren't retried on every redraw.
+    looked_up: HashSet<Snowflake>,
+    status: String,
+    matcher: SkimMatcherV2,
+}
+
+/// Lets the user pick guilds to leave, then leaves them the same way
+/// `guilds leave` does.
+pub async fn run(client: &DiscordClient) -> Result<Exit> {
+    let guilds = client.get_guilds().await?;
+    if guilds.is_empty() {
+        println!("No guilds found.");
+        return Ok(Exit::Success);
+    }
+
+    let mut app = App::new(client, guilds);
+    let mut terminal = ratatui::init();
+    let chosen = app.run(&mut terminal);
+    ratatui::restore();
+
+    let Some(chosen) = chosen? else {
+        return Ok(Exit::Aborted);
+    };
+
+    let action = Action::Leave { force: false };
+    let plan = action.plan(
+        client.is_dry_run(),
+        true,
+        chosen
+            .iter()
+            .map(|guild| PlannedGuild {
+                id: guild.id,
+                name: Some(guild.name.clone()),
+            })
+            .collect(),
+    );
+    let mut log = RunLog::start(client.journal(), &plan);
+    let pending = plan.guilds.iter().enumerate().collect();
+    commands::run_planned(
+        client,
+        action,
+        &chosen,
+        pending,
+        &mut log,
+        None,
+        OutputFormat::Text,
+    )
+    .await
+}
+
+impl<'a> App<'a> {
+    fn new(client: &'a DiscordClient, guilds: Vec<Guild>) -> Self {
+        let mut app = Self {
+            client,
+            guilds,
+            view: Vec::new(),
+            table: TableState::default().with_selected(0),
+            selected: HashSet::new(),
+            anchor: None,
+            query: String::new(),
+            sort: SortKey::Name,
+            descending: false,
+            mode: Mode::Browse,
+            details: false,
+            looked_up: HashSet::new(),
+            status: String::new(),
+            matcher: SkimMatcherV2::default().ignore_case(),
+        };
+        app.refresh();
+        app
+    }
+
+    /// Runs until the user confirms a selection, returning it, or quits.
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<Option<Vec<Guild>>> {
+        loop {
+            if self.details {
+                if let Some(index) = self.current() {
+                    self.fetch_members(vec![index]);
+                }
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                return Ok(None);
+            }
+
+            self.status.clear();
+            match self.mode {
+                Mode::Search => self.search_key(key),
+                Mode::Browse => {
+                    if !self.browse_key(key) {
+                        return Ok(None);
+                    }
+                }
+                Mode::Confirm => match key.code {
+                    KeyCode::Char('y') => {
+                        return Ok(Some(
+                            self.guilds
+                                .iter()
+                                .filter(|guild| self.selected.contains(&guild.id))
+                                .cloned()
+                                .collect(),
+                        ));
+                    }
+                    KeyCode::Char('n') | KeyCode::Esc => self.mode = Mode::Browse,
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    fn search_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Enter | KeyCode::Esc => self.mode = Mode::Browse,
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.refresh();
+            }
+            KeyCode::Char(c) => {
+                self.query.push(c);
+                self.refresh();
+            }
+            _ => {}
+        }
+    }
+
+    /// Handles a key while browsing. Returns `false` to quit.
+    fn browse_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Esc if self.query.is_empty() => return false,
+            KeyCode::Esc => {
+                self.query.clear();
+                self.refresh();
+            }
+            KeyCode::Char('/') => self.mode = Mode::Search,
+            KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+            KeyCode::PageDown => self.move_by(10),
+            KeyCode::PageUp => self.move_by(-10),
+            KeyCode::Home | KeyCode::Char('g') => self.table.select(Some(0)),
+            KeyCode::End | KeyCode::Char('G') => {
+                self.table.select(Some(self.view.len().saturating_sub(1)))
+            }
+            KeyCode::Char(' ') => self.toggle(),
+            KeyCode::Char('v') => self.select_range(),
+            KeyCode::Char('a') => self.select_all(self.view.clone()),
+            KeyCode::Char('A') => self.selected.clear(),
+            KeyCode::Char('s') => {
+                self.sort = self.sort.next();
+                if self.sort == SortKey::Joined {
+                    self.fetch_members((0..self.guilds.len()).collect());
+                }
+                self.refresh();
+            }
+            KeyCode::Char('d') => self.details = !self.details,
+            KeyCode::Char('r') => {
+                self.descending = !self.descending;
+                self.refresh();
+            }
+            KeyCode::Enter if self.selected.is_empty() => {
+                self.status = "Select guilds with space first.".to_string();
+            }
+            KeyCode::Enter => self.mode = Mode::Confirm,
+            _ => {}
+        }
+        true
+    }
+
+    /// Rebuilds the view from the search and sort order, keeping the cursor
+    /// on the same guild where possible.
+    fn refresh(&mut self) {
+        let current = self.current();
+
+        let mut scored: Vec<(usize, i64)> = self
+            .guilds
+            .iter()
+            .enumerate()
+            .filter_map(|(i, guild)| {
+                if self.query.is_empty() {
+                    return Some((i, 0));
+                }
+                self.matcher
+                    .fuzzy_match(&guild.name, &self.query)
+                    .map(|score| (i, score))
+            })
+            .collect();
+
+        scored.sort_by(|(a, a_score), (b, b_score)| {
+            let (a, b) = (&self.guilds[*a], &self.guilds[*b]);
+            let by_key = match self.sort {
+                SortKey::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+                SortKey::Members => a.approximate_member_count.cmp(&b.approximate_member_count),
+                SortKey::Online => a
+                    .approximate_presence_count
+                    .cmp(&b.approximate_presence_count),
+                SortKey::Joined => a.joined_at().cmp(&b.joined_at()),
+                SortKey::Id => a.id.cmp(&b.id),
+            };
+            let by_key = if self.descending {
+                by_key.reverse()
+            } else {
+                by_key
+            };
+            // The best fuzzy matches come first while searching.
+            b_score.cmp(a_score).then(by_key)
+        });
+
+        self.view = scored.into_iter().map(|(i, _)| i).collect();
+        self.anchor = None;
+        let position = current
+            .and_then(|current| self.view.iter().position(|&i| i == current))
+            .unwrap_or(0);
+        self.table.select(Some(position));
+    }
+
+    /// Fills in the membership of the guilds at `indices` that haven't
+    /// been looked up yet, blocking the UI until it's done.
+    fn fetch_members(&mut self, indices: Vec<usize>) {
+        let indices: Vec<_> = indices
+            .into_iter()
+            .filter(|&i| self.looked_up.insert(self.guilds[i].id))
+            .collect();
+        if indices.is_empty() {
+            return;
+        }
+
+        let client = self.client;
+        let ids: Vec<_> = indices.iter().map(|&i| self.guilds[i].id).collect();
+        let members: Vec<_> = block_on(
+            stream::iter(ids)
+                .map(|id| client.current_member(id))
+                .buffered(client.concurrency())
+                .collect(),
+        );
+
+        let mut failed = 0;
+        for (index, member) in indices.into_iter().zip(members) {
+            match member {
+                Ok(member) => self.guilds[index].member = Some(member),
+                Err(_) => failed += 1,
+            }
+        }
+        if failed > 0 {
+            self.status = format!("Couldn't get your membership of {} guild(s).", failed);
+        }
+    }
+
+    /// The index into `guilds` under the cursor.
+    fn current(&self) -> Option<usize> {
+        self.table
+            .selected()
+            .and_then(|row| self.view.get(row))
+            .copied()
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        if self.view.is_empty() {
+            return;
+        }
+        let row = self.table.selected().unwrap_or(0);
+        let row = row.saturating_add_signed(delta).min(self.view.len() - 1);
+        self.table.select(Some(row));
+    }
+
+    /// Selects a guild unless it can't be left, saying why not.
+    fn select(&mut self, index: usize) -> bool {
+        let guild = &self.guilds[index];
+        if guild.owner {
+            self.status = format!(
+                "You own {}, so it can't be left. Use `guilds transfer` or `guilds delete`.",
+                guild.name
+            );
+            return false;
+        }
+        if let Some(reason) = self.client.allowlist().protects(guild) {
+            self.status = format!("{} is protected: {}.", guild.name, reason);
+            return false;
+        }
+        self.selected.insert(guild.id);
+        true
+    }
+
+    fn toggle(&mut self) {
+        let Some(index) = self.current() else {
+            return;
+        };
+        if !self.selected.remove(&self.guilds[index].id) {
+            self.select(index);
+        }
+        self.anchor = self.table.selected();
+        self.move_by(1);
+    }
+
+    /// Selects every row between the last toggled one and the cursor.
+    fn select_range(&mut self) {
+        let (Some(anchor), Some(row)) = (self.anchor, self.table.selected()) else {
+            self.status = "Toggle a guild with space first to start a range.".to_string();
+            return;
+        };
+        let (start, end) = (anchor.min(row), anchor.max(row));
+        self.select_all(self.view[start..=end.min(self.view.len() - 1)].to_vec());
+    }
+
+    fn select_all(&mut self, indices: Vec<usize>) {
+        let skipped = indices.into_iter().filter(|&i| !self.select(i)).count();
+        self.status = match skipped {
+            0 => String::new(),
+            skipped => format!("Skipped {} guild(s) that can't be left.", skipped),
+        };
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [search, main, help] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        self.draw_search(frame, search);
+        if self.mode == Mode::Confirm {
+            self.draw_confirm(frame, main);
+        } else if !self.details {
+            self.draw_list(frame, main);
+        } else {
+            let [list, details] =
+                Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+                    .areas(main);
+            self.draw_list(frame, list);
+            self.draw_details(frame, details);
+        }
+        self.draw_help(frame, help);
+    }
+
+    fn draw_search(&self, frame: &mut Frame, area: Rect) {
+        let style = if self.mode == Mode::Search {
+            Style::new().fg(Color::Yellow)
+        } else {
+            Style::new()
+        };
+        let title = format!(
+            " Search ({} of {} guilds, sorted by {}{}) ",
+            self.view.len(),
+            self.guilds.len(),
+            self.sort.label(),
+            if self.descending { ", descending" } else { "" }
+        );
+        frame.render_widget(
+            Paragraph::new(self.query.as_str()).block(Block::bordered().title(title).style(style)),
+            area,
+        );
+    }
+
+    fn draw_list(&mut self, frame: &mut Frame, area: Rect) {
+        let rows = self.view.iter().map(|&i| {
+            let guild = &self.guilds[i];
+            let marker = if self.selected.contains(&guild.id) {
+                "[x]"
+            } else {
+                "[ ]"
+            };
+            let role = if guild.owner {
+                "owner"
+            } else if guild.permissions.is_admin() {
+                "admin"
+            } else {
+                ""
+            };
+            let dim = guild.owner || self.client.allowlist().protects(guild).is_some();
+            let row = Row::new([
+                Cell::from(marker),
+                Cell::from(initials(&guild.name)).bold(),
+                Cell::from(guild.name.as_str()),
+                Cell::from(role),
+                Cell::from(count(guild.approximate_member_count)),
+            ]);
+            if dim {
+                row.dark_gray()
+            } else {
+                row
+            }
+        });
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(3),
+                Constraint::Length(4),
+                Constraint::Fill(1),
+                Constraint::Length(5),
+                Constraint::Length(9),
+            ],
+        )
+        .header(Row::new(["", "", "Name", "", "Members"]).underlined())
+        .block(Block::bordered().title(format!(" Guilds ({} selected) ", self.selected.len())))
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+
+        frame.render_stateful_widget(table, area, &mut self.table);
+    }
+
+    fn draw_details(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title(" Details ");
+        let Some(guild) = self.current().map(|i| &self.guilds[i]) else {
+            frame.render_widget(block, area);
+            return;
+        };
+
+        let field = |name: &'static str, value: String| {
+            Line::from(vec![
+                Span::from(format!("{:<12}", name)).bold(),
+                value.into(),
+            ])
+        };
+        let mut lines = vec![
+            Line::from(guild.name.as_str()).bold(),
+            Line::default(),
+            field("ID", guild.id.to_string()),
+            field(
+                "Created",
+                guild.id.created_at().format("%Y-%m-%d").to_string(),
+            ),
+            field("Owner", yes_no(guild.owner)),
+            field("Admin", yes_no(guild.permissions.is_admin())),
+            field("Permissions", guild.permissions.0.to_string()),
+            field("Members", count(guild.approximate_member_count)),
+            field("Online", count(guild.approximate_presence_count)),
+            field("Features", guild.features.join(", ")),
+        ];
+        if let Some(member) = &guild.member {
+            lines.push(Line::default());
+            lines.push(field(
+                "Joined",
+                member.joined_at.map_or_else(
+                    || "unknown".to_string(),
+                    |joined_at| joined_at.format("%Y-%m-%d %H:%M").to_string(),
+                ),
+            ));
+            lines.push(field(
+                "Nickname",
+                member.nick.clone().unwrap_or_else(|| "none".to_string()),
+            ));
+            lines.push(field("Roles", member.roles.len().to_string()));
+            lines.push(field("Pending", yes_no(member.pending)));
+        }
+        if let Some(reason) = self.client.allowlist().protects(guild) {
+            lines.push(Line::default());
+            lines.push(Line::from(format!("Protected: {}", reason)).yellow());
+        }
+
+        frame.render_widget(
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: false })
+                .block(block),
+            area,
+        );
+    }
+
+    fn draw_confirm(&self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .guilds
+            .iter()
+            .filter(|guild| self.selected.contains(&guild.id))
+            .map(|guild| ListItem::new(format!("{}  {}", guild.id, guild)))
+            .collect();
+        let title = if self.client.is_dry_run() {
+            format!(
+                " DRY RUN: would leave these {} guild(s), changing nothing ",
+                items.len()
+            )
+        } else {
+            format!(" Leave these {} guild(s)? ", items.len())
+        };
+
+        frame.render_widget(
+            List::new(items).block(Block::bordered().title(title).red()),
+            area,
+        );
+    }
+
+    fn draw_help(&self, frame: &mut Frame, area: Rect) {
+        let help = match self.mode {
+            _ if !self.status.is_empty() => Line::from(self.status.as_str()).yellow(),
+            Mode::Browse => Line::from(
+                "space select  v range  a all  A none  / search  s sort  r reverse  d details  enter leave  q quit",
+            ),
+            Mode::Search => Line::from("type to search  enter/esc done"),
+            Mode::Confirm => Line::from("y leave them  n go back").bold(),
+        };
+        frame.render_widget(Paragraph::new(help).dark_gray(), area);
+    }
+}
+
+/// Runs a request from the synchronous event loop.
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(future))
+}
+
+/// The letters Discord shows in place of a missing guild icon.
+fn initials(name: &str) -> String {
+    name.split_whitespace()
+        .filter_map(|word| word.chars().next())
+        .take(4)
+        .collect()
+}
+
+fn count(count: Option<u64>) -> String {
+    count.map_or_else(|| "?".to_string(), |count| count.to_string())
+}
+
+fn yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_string()
+}
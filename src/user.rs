@@ -0,0 +1,67 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::snowflake::Snowflake;
+
+/// The parts of a Discord user object we care about.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub id: Snowflake,
+    pub username: String,
+    #[serde(default)]
+    pub global_name: Option<String>,
+}
+
+impl fmt::Display for User {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.global_name {
+            Some(global_name) => write!(f, "{} (@{})", global_name, self.username),
+            None => write!(f, "@{}", self.username),
+        }
+    }
+}
+
+/// The account a token belongs to, as returned by `GET /users/@me`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CurrentUser {
+    pub id: Snowflake,
+    pub username: String,
+    #[serde(default)]
+    pub global_name: Option<String>,
+    #[serde(default)]
+    pub mfa_enabled: bool,
+    /// Whether the email address is verified. Missing for bots.
+    #[serde(default)]
+    pub verified: Option<bool>,
+    /// 0 for none, 1 for Nitro Classic, 2 for Nitro and 3 for Nitro Basic.
+    #[serde(default)]
+    pub premium_type: Option<u8>,
+    #[serde(default)]
+    pub flags: u64,
+    #[serde(default)]
+    pub locale: Option<String>,
+    #[serde(default)]
+    pub bot: bool,
+}
+
+impl CurrentUser {
+    pub fn premium(&self) -> &'static str {
+        match self.premium_type {
+            None | Some(0) => "none",
+            Some(1) => "Nitro Classic",
+            Some(2) => "Nitro",
+            Some(3) => "Nitro Basic",
+            Some(_) => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for CurrentUser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.global_name {
+            Some(global_name) => write!(f, "{} (@{})", global_name, self.username),
+            None => write!(f, "@{}", self.username),
+        }
+    }
+}
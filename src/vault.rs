@@ -0,0 +1,276 @@
+//! Tokens kept encrypted under a passphrase in the config directory.
+//!
+//! The key is derived from the passphrase with Argon2id and every token is
+//! sealed with ChaCha20-Poly1305 under its own random nonce, bound to its
+//! name. Names are stored in the clear so the vault can be listed without
+//! unlocking it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::config::config_dir;
+use crate::error::{Error, Result};
+use crate::prompt;
+use crate::token::Token;
+
+/// Encrypted under the key to tell a wrong passphrase from a right one,
+/// even while the vault holds no tokens.
+const CHECK: &[u8] = b"discord-manager-rust vault";
+const CHECK_NAME: &str = "";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Kdf {
+    salt: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl Kdf {
+    fn generate() -> Self {
+        let mut salt = [0; 16];
+        OsRng.fill_bytes(&mut salt);
+        Self {
+            salt: BASE64.encode(salt),
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+
+    fn derive(&self, passphrase: &str) -> Result<Zeroizing<[u8; 32]>> {
+        let salt = decode(&self.salt)?;
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|e| Error::Vault(format!("invalid key derivation parameters: {}", e)))?;
+
+        let mut key = Zeroizing::new([0; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, key.as_mut())
+            .map_err(|e| Error::Vault(format!("key derivation failed: {}", e)))?;
+        Ok(key)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Sealed {
+    nonce: String,
+    ciphertext: String,
+}
+
+impl Sealed {
+    /// Encrypts `plaintext`, authenticating `name` along with it so sealed
+    /// values can't be swapped between names.
+    fn seal(cipher: &ChaCha20Poly1305, name: &str, plaintext: &[u8]) -> Result<Self> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: name.as_bytes(),
+                },
+            )
+            .map_err(|_| Error::Vault("encryption failed".to_string()))?;
+        Ok(Self {
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        })
+    }
+
+    fn open(&self, cipher: &ChaCha20Poly1305, name: &str) -> Result<Zeroizing<Vec<u8>>> {
+        let nonce = decode(&self.nonce)?;
+        if nonce.len() != 12 {
+            return Err(Error::Vault("corrupt nonce".to_string()));
+        }
+        cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &decode(&self.ciphertext)?,
+                    aad: name.as_bytes(),
+                },
+            )
+            .map(Zeroizing::new)
+            .map_err(|_| Error::Vault("wrong passphrase or corrupt vault".to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VaultFile {
+    kdf: Kdf,
+    check: Sealed,
+    #[serde(default)]
+    tokens: BTreeMap<String, Sealed>,
+}
+
+pub struct Vault {
+    path: PathBuf,
+    file: Option<VaultFile>,
+    /// Set once the vault is unlocked, so the passphrase is asked for at
+    /// most once per run.
+    cipher: Option<ChaCha20Poly1305>,
+}
+
+impl Vault {
+    /// `<config dir>/discord-manager-rust/vault.json`.
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(config_dir()?.join("vault.json"))
+    }
+
+    /// Loads the vault without unlocking it. A missing file is an empty vault.
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = match fs::read(path) {
+            Ok(bytes) => Some(serde_json::from_slice(&bytes).map_err(|e| {
+                Error::Vault(format!("{} is not a valid vault: {}", path.display(), e))
+            })?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            cipher: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.file
+            .iter()
+            .flat_map(|file| file.tokens.keys())
+            .map(String::as_str)
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names().is_empty()
+    }
+
+    /// Asks for the passphrase, or for a new one if the vault doesn't exist
+    /// yet. Does nothing if it's already unlocked.
+    pub fn unlock(&mut self) -> Result<()> {
+        if self.cipher.is_some() {
+            return Ok(());
+        }
+
+        match &self.file {
+            Some(file) => {
+                let passphrase = Zeroizing::new(prompt::secret("Vault passphrase")?);
+                let key = file.kdf.derive(&passphrase)?;
+                let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()));
+                file.check.open(&cipher, CHECK_NAME)?;
+                self.cipher = Some(cipher);
+            }
+            None => {
+                let passphrase = Zeroizing::new(prompt::secret("New vault passphrase")?);
+                if passphrase.is_empty() {
+                    return Err(Error::Vault("the passphrase can't be empty".to_string()));
+                }
+                if *passphrase != *prompt::secret("Repeat the passphrase")? {
+                    return Err(Error::Vault("the passphrases don't match".to_string()));
+                }
+
+                let kdf = Kdf::generate();
+                let key = kdf.derive(&passphrase)?;
+                let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()));
+                self.file = Some(VaultFile {
+                    check: Sealed::seal(&cipher, CHECK_NAME, CHECK)?,
+                    kdf,
+                    tokens: BTreeMap::new(),
+                });
+                self.cipher = Some(cipher);
+            }
+        }
+        Ok(())
+    }
+
+    /// Decrypts the token stored under `name`, unlocking the vault first.
+    pub fn get(&mut self, name: &str) -> Result<Option<Token>> {
+        let Some(sealed) = self.file.as_ref().and_then(|file| file.tokens.get(name)) else {
+            return Ok(None);
+        };
+        let sealed = sealed.clone();
+
+        self.unlock()?;
+        let cipher = self.cipher.as_ref().expect("unlocked above");
+        let plaintext = sealed.open(cipher, name)?;
+        let token = String::from_utf8(plaintext.to_vec())
+            .map_err(|_| Error::Vault(format!("the token for {} is corrupt", name)))?;
+        Ok(Some(Token::new(token)))
+    }
+
+    /// Stores a token under `name`, replacing any already there.
+    pub fn insert(&mut self, name: &str, token: &Token) -> Result<()> {
+        self.unlock()?;
+        let cipher = self.cipher.as_ref().expect("unlocked above");
+        let sealed = Sealed::seal(cipher, name, Zeroizing::new(token.expose()).as_bytes())?;
+        self.file
+            .as_mut()
+            .expect("unlocking creates the file")
+            .tokens
+            .insert(name.to_string(), sealed);
+        Ok(())
+    }
+
+    /// Forgets the token stored under `name`. Needs no passphrase.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.file
+            .as_mut()
+            .is_some_and(|file| file.tokens.remove(name).is_some())
+    }
+
+    /// Writes the vault back, readable only by the current user. It is
+    /// written to a temporary file that then replaces the old one, so a
+    /// crash or a full disk midway can't lose the tokens already stored.
+    pub fn save(&self) -> Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Left behind by an earlier crash, possibly with looser permissions
+        // than a freshly created file gets.
+        let temp = self.path.with_extension("json.tmp");
+        match fs::remove_file(&temp) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut out = options.open(&temp)?;
+        io::Write::write_all(&mut out, &serde_json::to_vec_pretty(file)?)?;
+        out.sync_all()?;
+        drop(out);
+
+        fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+}
+
+fn decode(value: &str) -> Result<Vec<u8>> {
+    BASE64
+        .decode(value)
+        .map_err(|e| Error::Vault(format!("corrupt vault: {}", e)))
+}